@@ -0,0 +1,781 @@
+// Handles the command line interface, used when the app is run with arguments
+
+use std::io::BufRead;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use encrypt::EncryptionKey;
+use serde_json::json;
+
+use crate::clock::{self, Clock, FixedClock};
+use crate::encoding::SecretEncoding;
+use crate::file;
+use crate::key::{CodeOptions, Key};
+use crate::otp::{self, OTPMethod};
+use crate::qr;
+
+/// Environment variable the password can be read from, instead of a prompt
+pub const PASSWORD_VAR: &str = "AUTHOGONAL_PASSWORD";
+
+const USAGE: &str = "Usage: authogonal [--json] [--password-stdin] [--data-dir <dir>] <command>
+
+Commands:
+  list                     List the ID and name of all stored keys
+  code <key> [--at <time>] Show the current code for a key, or its code at a time given
+                           as a UNIX timestamp or RFC 3339 date (e.g. 2024-01-01T12:00:00Z)
+  increment <key>          Increment a HOTP key's counter and show the new code
+  add --account <account> --secret <secret> [--issuer <issuer>] [options]
+                           Add a key from its secret
+  add --uri <uri> [--issuer <issuer>] [--account <account>]
+                           Add keys from an otpauth://, steam:// or Google Authenticator
+                           otpauth-migration:// URI, optionally replacing the issuer or
+                           account name of a single key
+  remove <key>             Remove a key
+  help                     Show this message
+
+Keys are given by name, either as issuer:account or as the account name alone,
+or by ID when several keys share a name. --name is accepted in place of --account.
+
+Add options:
+  --encoding <base32|hex|base64>   Encoding of the secret (default base32)
+  --method <totp|hotp|steam>       Code generation method (default totp)
+  --counter <count>                Initial HOTP counter (default 0)
+  --hash <sha1|sha256|sha512>      Hash function (default sha1)
+  --digits <length>                Code length (default 6)
+  --period <seconds>               TOTP interval (default 30)
+
+The password is read from stdin with --password-stdin, otherwise from the
+AUTHOGONAL_PASSWORD environment variable, otherwise from a prompt.
+Only add creates a new key file if none exists.
+
+Files are stored in the directory given by --data-dir, otherwise the
+AUTHOGONAL_DATA_DIR environment variable, otherwise beside the executable if a
+file named portable is there, otherwise the platform's data & config
+directories. --data-dir can also be given without a command to open the app.";
+
+/// The action requested by the user
+#[derive(Debug, PartialEq)]
+enum Command {
+    List,
+    Code(String, Option<i64>), // Key name and the UNIX timestamp to generate the code at, if not now
+    Increment(String),
+    Add(Key),
+    AddUri(String, Option<String>, Option<String>), // URI and optional issuer & account name to replace the ones in the URI
+    Remove(String),
+    Help,
+}
+
+/// All parsed command line arguments
+#[derive(Debug, PartialEq)]
+struct Args {
+    command: Command,
+    json: bool,
+    password_stdin: bool,
+}
+
+/// Flags that apply to every command, which can be given anywhere a flag can
+#[derive(Default)]
+struct GlobalFlags {
+    json: bool,
+    password_stdin: bool,
+}
+
+impl GlobalFlags {
+    /// Sets the flag an argument names, returning whether it was a global flag
+    fn set(&mut self, arg: &str) -> bool {
+        match arg {
+            "--json" => self.json = true,
+            "--password-stdin" => self.password_stdin = true,
+            _ => return false,
+        }
+        true
+    }
+}
+
+/// Runs the command line interface with the arguments passed to the program, excluding the program name
+/// Output is printed to stdout, and errors to stderr (or stdout as JSON if requested)
+pub fn run(args: Vec<String>) -> ExitCode {
+    // Arguments that can't be parsed are only checked for the flag
+    let mut json = args.iter().any(|a| a == "--json");
+    let result = parse_args(args).and_then(|args| {
+        json = args.json;
+        execute(args)
+    });
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            if json {
+                println!("{}", json!({ "error": e.to_string() }));
+            } else {
+                eprintln!("error: {}", e);
+                if let Error::Usage(_) = e {
+                    eprintln!("\n{}", USAGE);
+                }
+            }
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Removes the `--data-dir <dir>` or `--data-dir=<dir>` argument, returning the directory
+/// This is handled separately to the other arguments, as it also applies when opening the GUI
+pub fn take_data_dir(args: &mut Vec<String>) -> Option<PathBuf> {
+    let i = args
+        .iter()
+        .position(|a| a == "--data-dir" || a.starts_with("--data-dir="))?;
+    if let Some(dir) = args[i].strip_prefix("--data-dir=") {
+        let dir = PathBuf::from(dir);
+        args.remove(i);
+        return Some(dir);
+    }
+    // Without a value, the argument is left to be reported as invalid
+    if i + 1 >= args.len() {
+        return None;
+    }
+    let dir = PathBuf::from(args.remove(i + 1));
+    args.remove(i);
+    Some(dir)
+}
+
+/// Parses the arguments into a [Command] and global flags
+/// Global flags can appear anywhere other than as the value of another flag
+fn parse_args(args: Vec<String>) -> Result<Args, Error> {
+    let mut globals = GlobalFlags::default();
+    let mut rest = args.into_iter();
+    let command = loop {
+        match rest.next() {
+            Some(arg) if globals.set(&arg) => continue,
+            arg => break arg,
+        }
+    };
+
+    let command = match command.as_deref() {
+        Some("list") => {
+            no_args(&mut rest, &mut globals)?;
+            Command::List
+        }
+        Some("code") => parse_code(&mut rest, &mut globals)?,
+        Some("increment") => Command::Increment(name_arg(&mut rest, &mut globals)?),
+        Some("remove") => Command::Remove(name_arg(&mut rest, &mut globals)?),
+        Some("add") => parse_add(&mut rest, &mut globals)?,
+        Some("help") | Some("--help") | Some("-h") => Command::Help,
+        Some(c) => return Err(Error::Usage(format!("Unknown command {}", c))),
+        None => return Err(Error::Usage(String::from("No command given"))),
+    };
+
+    Ok(Args {
+        command,
+        json: globals.json,
+        password_stdin: globals.password_stdin,
+    })
+}
+
+/// Takes the positional arguments following a command, setting any global flags among them
+fn positional(rest: &mut impl Iterator<Item = String>, globals: &mut GlobalFlags) -> Vec<String> {
+    rest.filter(|arg| !globals.set(arg)).collect()
+}
+
+/// Checks that a command that takes no arguments wasn't given any
+fn no_args(
+    rest: &mut impl Iterator<Item = String>,
+    globals: &mut GlobalFlags,
+) -> Result<(), Error> {
+    match positional(rest, globals).into_iter().next() {
+        Some(extra) => Err(Error::Usage(format!("Unexpected argument {}", extra))),
+        None => Ok(()),
+    }
+}
+
+/// Takes the single key name argument used by most commands
+fn name_arg(
+    rest: &mut impl Iterator<Item = String>,
+    globals: &mut GlobalFlags,
+) -> Result<String, Error> {
+    let mut rest = positional(rest, globals).into_iter();
+    let name = rest
+        .next()
+        .ok_or(Error::Usage(String::from("Missing key name")))?;
+    match rest.next() {
+        Some(extra) => Err(Error::Usage(format!("Unexpected argument {}", extra))),
+        None => Ok(name),
+    }
+}
+
+/// Parses the key name & optional time of the code command
+fn parse_code(
+    rest: &mut impl Iterator<Item = String>,
+    globals: &mut GlobalFlags,
+) -> Result<Command, Error> {
+    let mut at = None;
+    let mut others = Vec::new();
+    while let Some(arg) = rest.next() {
+        if arg == "--at" {
+            let time = rest
+                .next()
+                .ok_or(Error::Usage(String::from("Missing value for --at")))?;
+            let timestamp = clock::parse_time(&time)
+                .ok_or_else(|| Error::Usage(format!("Invalid time {}", time)))?;
+            at = Some(timestamp);
+        } else {
+            others.push(arg);
+        }
+    }
+    let name = name_arg(&mut others.into_iter(), globals)?;
+    Ok(Command::Code(name, at))
+}
+
+/// Parses the flags of the add command into either a [Key] or a URI
+fn parse_add(
+    args: &mut impl Iterator<Item = String>,
+    globals: &mut GlobalFlags,
+) -> Result<Command, Error> {
+    // Every other flag takes a value, so they are read in pairs, with the value taken as it is even if it looks like a flag
+    let mut flags = Vec::new();
+    while let Some(flag) = args.next() {
+        if globals.set(&flag) {
+            continue;
+        }
+        let value = args
+            .next()
+            .ok_or_else(|| Error::Usage(format!("Missing value for {}", flag)))?;
+        flags.push((flag, value));
+    }
+    let get = |name: &str| {
+        flags
+            .iter()
+            .find(|(f, _)| f == name)
+            .map(|(_, v)| v.as_str())
+    };
+
+    // Reject flags that aren't recognised, so typos aren't silently ignored
+    let known = [
+        "--uri",
+        "--issuer",
+        "--account",
+        "--name",
+        "--secret",
+        "--encoding",
+        "--method",
+        "--counter",
+        "--hash",
+        "--digits",
+        "--period",
+    ];
+    if let Some((flag, _)) = flags.iter().find(|(f, _)| !known.contains(&f.as_str())) {
+        return Err(Error::Usage(format!("Unknown option {}", flag)));
+    }
+
+    // --name is kept from before issuers were separate, and sets the account name
+    let issuer = get("--issuer").map(String::from);
+    let account = get("--account").or(get("--name")).map(String::from);
+
+    if let Some(uri) = get("--uri") {
+        return Ok(Command::AddUri(uri.to_string(), issuer, account));
+    }
+
+    let account = account.ok_or(Error::Usage(String::from("Missing --account")))?;
+    let secret = get("--secret").ok_or(Error::Usage(String::from("Missing --secret")))?;
+
+    let encoding = match get("--encoding").map(str::to_ascii_lowercase).as_deref() {
+        None | Some("base32") => SecretEncoding::Base32,
+        Some("hex") => SecretEncoding::Hex,
+        Some("base64") => SecretEncoding::Base64,
+        Some(e) => return Err(Error::Usage(format!("Unknown encoding {}", e))),
+    };
+    let counter = number_arg(get("--counter"), "--counter")?;
+    let method = match get("--method").map(str::to_ascii_lowercase).as_deref() {
+        None | Some("totp") => OTPMethod::TOTP,
+        Some("hotp") => OTPMethod::HOTP(counter.unwrap_or(0)),
+        Some("steam") => OTPMethod::Steam,
+        Some(m) => return Err(Error::Usage(format!("Unknown method {}", m))),
+    };
+    let hash = match get("--hash").map(str::to_ascii_lowercase).as_deref() {
+        None => None,
+        Some("sha1") => Some(hash::HashFn::SHA1),
+        Some("sha256") => Some(hash::HashFn::SHA256),
+        Some("sha512") => Some(hash::HashFn::SHA512),
+        Some(h) => return Err(Error::Usage(format!("Unknown hash function {}", h))),
+    };
+
+    // Steam Guard codes have fixed options
+    let options = if method == OTPMethod::Steam {
+        CodeOptions::steam()
+    } else {
+        CodeOptions::new_or_default(
+            Some(method),
+            hash,
+            number_arg(get("--digits"), "--digits")?,
+            number_arg(get("--period"), "--period")?,
+        )
+    };
+
+    let mut key = Key::new(
+        Key::decode_secret(secret, encoding)?,
+        issuer.unwrap_or_default(),
+        account,
+        options,
+    );
+    key.encoding = encoding;
+    Ok(Command::Add(key))
+}
+
+/// Parses an optional numeric flag value
+fn number_arg<T: std::str::FromStr>(value: Option<&str>, flag: &str) -> Result<Option<T>, Error> {
+    value
+        .map(|v| {
+            v.parse()
+                .map_err(|_| Error::Usage(format!("Invalid value for {}", flag)))
+        })
+        .transpose()
+}
+
+/// Runs a parsed command, unlocking the key file if needed
+fn execute(args: Args) -> Result<(), Error> {
+    if args.command == Command::Help {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+
+    // Only adding a key creates the key file, so a mistyped directory isn't given a new one
+    let create = matches!(args.command, Command::Add(_) | Command::AddUri(..));
+    let e_key = unlock(args.password_stdin, create)?;
+    // Settings are only read, so commands don't write a settings file
+    let clock = file::options::read().unwrap_or_default().clock();
+
+    match args.command {
+        Command::List => {
+            let keys = file::keys::load(&e_key)?;
+            if args.json {
+                let list: Vec<_> = keys.iter().map(key_json).collect();
+                println!("{}", serde_json::Value::Array(list));
+            } else {
+                for key in keys {
+                    println!("{}\t{}", key.id, key.label());
+                }
+            }
+        }
+        Command::Code(name, at) => {
+            let key = find(&name, &e_key)?;
+            // A chosen time is used in place of the clock, so the time offset setting doesn't apply to it
+            match at.and_then(FixedClock::from_timestamp) {
+                Some(fixed) => print_code(&key, &fixed, args.json),
+                None => print_code(&key, &*clock, args.json),
+            }
+        }
+        Command::Increment(name) => {
+            let mut key = find(&name, &e_key)?;
+            if !matches!(key.options.method, OTPMethod::HOTP(_)) {
+                return Err(Error::NotHotp(name));
+            }
+            key.increment(&e_key)?;
+            print_code(&key, &*clock, args.json);
+        }
+        Command::Add(key) => print_added(&file::keys::add(&key, &e_key)?, args.json),
+        Command::AddUri(uri, issuer, account) => {
+            let codes = uri_keys(uri, issuer, account)?;
+            add_codes(codes, &e_key, args.json)?;
+        }
+        Command::Remove(name) => {
+            let key = find(&name, &e_key)?;
+            file::keys::remove(key.id, &e_key)?;
+            if args.json {
+                println!("{}", json!({ "removed": key.label(), "id": key.id }));
+            } else {
+                println!("Removed {}", key.label());
+            }
+        }
+        Command::Help => unreachable!(), // Handled before unlocking
+    }
+
+    Ok(())
+}
+
+/// Reads the password and checks it can decrypt the key file, creating the file if it doesn't exist & create is set
+fn unlock(password_stdin: bool, create: bool) -> Result<EncryptionKey, Error> {
+    let password = if password_stdin {
+        // Only the first line is used, without its line ending
+        let mut line = String::new();
+        std::io::stdin()
+            .lock()
+            .read_line(&mut line)
+            .map_err(Error::Password)?;
+        line.trim_end_matches(['\n', '\r']).to_string()
+    } else if let Ok(password) = std::env::var(PASSWORD_VAR) {
+        password
+    } else {
+        rpassword::prompt_password("Password: ").map_err(Error::Password)?
+    };
+
+    if create {
+        Ok(file::keys::unlock(&password)?)
+    } else {
+        Ok(file::keys::unlock_existing(&password)?)
+    }
+}
+
+/// Finds a stored key by name or ID
+fn find(name: &str, e_key: &EncryptionKey) -> Result<Key, Error> {
+    select(&file::keys::load(e_key)?, name)
+}
+
+/// Selects the key with a given name, or with a given ID if no key has that name
+/// A name is either the full issuer:account label or the account name alone
+/// Names aren't unique, so a name shared by several keys is an error
+fn select(keys: &[Key], name: &str) -> Result<Key, Error> {
+    let mut named = keys
+        .iter()
+        .filter(|k| k.label() == name || k.account == name);
+    match (named.next(), named.next()) {
+        (Some(key), None) => Ok(key.clone()),
+        (Some(_), Some(_)) => Err(Error::Ambiguous(name.to_string())),
+        (None, _) => name
+            .parse()
+            .ok()
+            .and_then(|id: u64| keys.iter().find(|k| k.id == id))
+            .cloned()
+            .ok_or_else(|| crate::Error::NotFound(name.to_string()).into()),
+    }
+}
+
+/// Parses a URI in the same way as one pasted into the add tab, so export URIs are also accepted
+/// The issuer & account name replace those in the URI, which is only allowed if it holds a single key
+fn uri_keys(
+    uri: String,
+    issuer: Option<String>,
+    account: Option<String>,
+) -> Result<qr::Codes, Error> {
+    let mut codes = qr::parse_codes(vec![Ok(uri)]);
+    if issuer.is_none() && account.is_none() {
+        return Ok(codes);
+    }
+    if codes.len() != 1 {
+        return Err(Error::Usage(String::from(
+            "--issuer and --account can only be used with a URI for a single key",
+        )));
+    }
+    if let Ok(key) = &mut codes[0] {
+        if let Some(issuer) = issuer {
+            key.issuer = issuer;
+        }
+        if let Some(account) = account {
+            key.account = account;
+        }
+    }
+    Ok(codes)
+}
+
+/// Adds each parsed key, reporting the ones that couldn't be added alongside those that were
+/// Fails with the first error if no key could be added
+fn add_codes(codes: qr::Codes, e_key: &EncryptionKey, json: bool) -> Result<(), Error> {
+    // Keys are imported together, so the key file is only saved once
+    let keys: Vec<Key> = codes
+        .iter()
+        .filter_map(|c| c.as_ref().ok())
+        .cloned()
+        .collect();
+    let mut imported = file::keys::import(&keys, e_key)?.into_iter();
+    // Parse errors are kept in place, so results are reported in the order the URI listed them
+    let results: Vec<_> = codes
+        .into_iter()
+        .map(|code| code.and_then(|_| imported.next().unwrap()))
+        .collect();
+
+    if !results.iter().any(Result::is_ok) {
+        if let Some(e) = results.into_iter().find_map(Result::err) {
+            return Err(e.into());
+        }
+        // An export without any keys has nothing to add
+        return Ok(());
+    }
+    for result in results {
+        match result {
+            Ok(key) => print_added(&key, json),
+            Err(e) if json => println!("{}", json!({ "error": e.to_string() })),
+            Err(e) => eprintln!("Couldn't add key: {}", e),
+        }
+    }
+    Ok(())
+}
+
+/// Prints a key's current code, along with the seconds it remains valid for if it is time based
+fn print_code(key: &Key, clock: &dyn Clock, json: bool) {
+    let code = otp::generate(key, clock);
+    let remaining = remaining(key, clock);
+    if json {
+        println!(
+            "{}",
+            json!({ "id": key.id, "name": key.label(), "code": code, "remaining": remaining })
+        );
+    } else {
+        println!("{}", code);
+    }
+}
+
+/// Seconds until a time based key's code changes, or None for counter based keys
+fn remaining(key: &Key, clock: &dyn Clock) -> Option<i64> {
+    if key.options.method.strip().is_timed() {
+        let interval = key.options.interval as i64;
+        Some(interval - clock.now().timestamp().rem_euclid(interval))
+    } else {
+        None
+    }
+}
+
+/// Prints the name of a key that has just been added
+fn print_added(key: &Key, json: bool) {
+    if json {
+        println!("{}", key_json(key));
+    } else {
+        println!("Added {}", key.label());
+    }
+}
+
+/// Describes a key as JSON, without its secret
+fn key_json(key: &Key) -> serde_json::Value {
+    let (method, counter) = match key.options.method {
+        OTPMethod::TOTP => ("totp", None),
+        OTPMethod::HOTP(c) => ("hotp", Some(c)),
+        OTPMethod::Steam => ("steam", None),
+    };
+    json!({
+        "id": key.id,
+        "name": key.label(),
+        "issuer": key.issuer,
+        "account": key.account,
+        "method": method,
+        "counter": counter,
+        "hash": format!("{:?}", key.options.hash),
+        "digits": key.options.length,
+        "interval": key.options.interval,
+    })
+}
+
+/// Error enum to handle errors with running a command
+#[derive(Debug)]
+enum Error {
+    Usage(String),            // Arguments are invalid, with a message describing why
+    Password(std::io::Error), // Password couldn't be read
+    NotHotp(String),          // Key to increment isn't counter based
+    Ambiguous(String),        // More than one key has the name given
+    Lib(crate::Error),        // Command failed
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Usage(m) => write!(f, "{}", m),
+            Self::Password(e) => write!(f, "Could not read password: {}", e),
+            Self::NotHotp(name) => write!(f, "{} is not a HOTP key", name),
+            Self::Ambiguous(name) => {
+                write!(f, "More than one key is named {}, use its ID instead", name)
+            }
+            Self::Lib(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<crate::Error> for Error {
+    fn from(e: crate::Error) -> Self {
+        Self::Lib(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &str) -> Vec<String> {
+        args.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn parse_code() {
+        let parsed = parse_args(args("--json code github --password-stdin")).unwrap();
+        assert_eq!(
+            parsed,
+            Args {
+                command: Command::Code(String::from("github"), None),
+                json: true,
+                password_stdin: true,
+            }
+        );
+    }
+
+    #[test]
+    fn parse_flag_values() {
+        // Flag names given as the value of another flag are kept as values
+        let parsed = parse_args(args("add --account --json --secret JBSWY3DP --json")).unwrap();
+        let Command::Add(key) = parsed.command else {
+            panic!("Expected add command");
+        };
+        assert_eq!(key.account, "--json");
+        assert!(parsed.json);
+        assert!(!parsed.password_stdin);
+
+        let parsed = parse_args(args(
+            "add --issuer --password-stdin --account a --secret JBSWY3DP",
+        ))
+        .unwrap();
+        let Command::Add(key) = parsed.command else {
+            panic!("Expected add command");
+        };
+        assert_eq!(key.issuer, "--password-stdin");
+        assert!(!parsed.password_stdin);
+    }
+
+    #[test]
+    fn parse_code_at() {
+        let parsed = parse_args(args("code github --at 59")).unwrap();
+        assert_eq!(
+            parsed.command,
+            Command::Code(String::from("github"), Some(59))
+        );
+        let parsed = parse_args(args("code --at 2009-02-13T23:31:30Z github")).unwrap();
+        assert_eq!(
+            parsed.command,
+            Command::Code(String::from("github"), Some(1234567890))
+        );
+
+        assert!(parse_args(args("code github --at")).is_err());
+        assert!(parse_args(args("code github --at yesterday")).is_err());
+        assert!(parse_args(args("code github --at 99999999999999999")).is_err());
+    }
+
+    #[test]
+    fn data_dir() {
+        let mut given = args("--json --data-dir /tmp/a list");
+        assert_eq!(take_data_dir(&mut given), Some(PathBuf::from("/tmp/a")));
+        assert_eq!(given, args("--json list"));
+
+        let mut given = args("--data-dir=/tmp/b");
+        assert_eq!(take_data_dir(&mut given), Some(PathBuf::from("/tmp/b")));
+        assert!(given.is_empty());
+
+        let mut given = args("--data-dir");
+        assert_eq!(take_data_dir(&mut given), None);
+        assert!(parse_args(given).is_err());
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert!(parse_args(args("")).is_err());
+        assert!(parse_args(args("generate github")).is_err());
+        assert!(parse_args(args("code")).is_err());
+        assert!(parse_args(args("code a b")).is_err());
+    }
+
+    #[test]
+    fn parse_add_secret() {
+        let parsed = parse_args(args(
+            "add --name test --secret 3132333435363738393031323334353637383930 --encoding hex --method hotp --counter 5 --digits 8",
+        ))
+        .unwrap();
+        let Command::Add(key) = parsed.command else {
+            panic!("Expected add command");
+        };
+        assert_eq!(key.secret, b"12345678901234567890");
+        assert_eq!(key.encoding, SecretEncoding::Hex);
+        assert_eq!(key.options.method, OTPMethod::HOTP(5));
+        assert_eq!(key.options.length, 8);
+    }
+
+    #[test]
+    fn parse_add_uri() {
+        let parsed = parse_args(args("add --uri steam://JBSWY3DPEHPK3PXP --name main")).unwrap();
+        assert_eq!(
+            parsed.command,
+            Command::AddUri(
+                String::from("steam://JBSWY3DPEHPK3PXP"),
+                None,
+                Some(String::from("main"))
+            )
+        );
+    }
+
+    #[test]
+    fn add_uri_keys() {
+        // Export URIs are accepted, with a key for each account exported
+        let export = String::from("otpauth-migration://offline?data=CgoKBUhlbGxvEgFhGAMgACgH");
+        let codes = uri_keys(export.clone(), None, None).unwrap();
+        assert_eq!(codes.len(), 3);
+        assert_eq!(codes[0].as_ref().unwrap().account, "a");
+        // Other batches of the export weren't given
+        assert!(codes[1..].iter().all(Result::is_err));
+        // The name can't be replaced when there are several keys
+        assert!(matches!(
+            uri_keys(export, None, Some(String::from("b"))),
+            Err(Error::Usage(_))
+        ));
+
+        let codes = uri_keys(
+            String::from("steam://JBSWY3DPEHPK3PXP"),
+            None,
+            Some(String::from("main")),
+        )
+        .unwrap();
+        assert_eq!(codes[0].as_ref().unwrap().account, "main");
+    }
+
+    #[test]
+    fn parse_add_issuer() {
+        let parsed = parse_args(args(
+            "add --issuer GitHub --account primm --secret JBSWY3DP",
+        ))
+        .unwrap();
+        let Command::Add(key) = parsed.command else {
+            panic!("Expected add command");
+        };
+        assert_eq!(key.issuer, "GitHub");
+        assert_eq!(key.account, "primm");
+    }
+
+    #[test]
+    fn parse_add_invalid() {
+        assert!(parse_args(args("add --name test")).is_err());
+        assert!(parse_args(args("add --name test --secret JBSWY3DP --algorithm sha1")).is_err());
+        assert!(parse_args(args("add --name test --secret JBSWY3DP --digits")).is_err());
+        assert!(parse_args(args("add --name test --secret 2082")).is_err());
+    }
+
+    #[test]
+    fn remaining_time() {
+        let key = Key::new(
+            vec![0],
+            String::new(),
+            String::from("test"),
+            Default::default(),
+        );
+        let clock = FixedClock::from_timestamp(59).unwrap();
+        assert_eq!(remaining(&key, &clock), Some(1));
+
+        let options = CodeOptions::new_or_default(Some(OTPMethod::HOTP(0)), None, None, None);
+        let key = Key::new(vec![0], String::new(), String::from("test"), options);
+        assert_eq!(remaining(&key, &clock), None);
+    }
+
+    #[test]
+    fn select_key() {
+        let key = |id, issuer: &str, account: &str| Key {
+            id,
+            issuer: issuer.to_string(),
+            account: account.to_string(),
+            ..Default::default()
+        };
+        let keys = [
+            key(1, "GitHub", "primm"),
+            key(2, "", "email"),
+            key(3, "", "email"),
+            key(4, "", "3"),
+        ];
+
+        assert_eq!(select(&keys, "GitHub:primm").unwrap().id, 1);
+        assert_eq!(select(&keys, "primm").unwrap().id, 1);
+        // Shared names must be given by ID
+        assert!(matches!(select(&keys, "email"), Err(Error::Ambiguous(_))));
+        assert_eq!(select(&keys, "2").unwrap().id, 2);
+        // Names take priority over IDs
+        assert_eq!(select(&keys, "3").unwrap().id, 4);
+        assert!(select(&keys, "5").is_err());
+    }
+}
@@ -0,0 +1,109 @@
+// Provides sources of the current time, so that time based codes aren't tied to the system clock
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+
+/// A source of the current time
+/// Clocks are shared between the app and every key's thread, so must be thread safe
+pub trait Clock: Send + Sync {
+    /// Returns the current time according to the clock
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// A [Clock] that reads the system time
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A [Clock] that is stopped at a single instant
+#[derive(Clone, Copy, Debug)]
+pub struct FixedClock(pub DateTime<Utc>);
+
+impl FixedClock {
+    /// Constructor for a [FixedClock] stopped at a UNIX timestamp (in seconds)
+    /// Returns None if the timestamp is out of range
+    pub fn from_timestamp(timestamp: i64) -> Option<Self> {
+        Utc.timestamp_opt(timestamp, 0).single().map(Self)
+    }
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.0
+    }
+}
+
+/// A [Clock] that runs a constant offset ahead of (or behind, if negative) another clock
+/// Useful for correcting a system clock that has drifted
+#[derive(Clone, Copy, Debug)]
+pub struct OffsetClock<C: Clock> {
+    clock: C,
+    offset: Duration,
+}
+
+impl<C: Clock> OffsetClock<C> {
+    /// Constructor for an [OffsetClock]
+    pub fn new(clock: C, offset: Duration) -> Self {
+        Self { clock, offset }
+    }
+}
+
+impl<C: Clock> Clock for OffsetClock<C> {
+    fn now(&self) -> DateTime<Utc> {
+        self.clock.now() + self.offset
+    }
+}
+
+/// Parses a time given as a UNIX timestamp (in seconds) or an RFC 3339 date, such as 2024-01-01T12:00:00Z
+/// Returns None if the time can't be read or is out of range
+pub fn parse_time(time: &str) -> Option<i64> {
+    let timestamp = match time.parse() {
+        Ok(timestamp) => timestamp,
+        Err(_) => DateTime::parse_from_rfc3339(time).ok()?.timestamp(),
+    };
+    // Checked so that a code can always be generated at the time
+    FixedClock::from_timestamp(timestamp).map(|_| timestamp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(parse_time("59"), Some(59));
+        assert_eq!(parse_time("-1"), Some(-1));
+        assert_eq!(parse_time("2009-02-13T23:31:30Z"), Some(1234567890));
+        assert_eq!(parse_time("2009-02-14T00:31:30+01:00"), Some(1234567890));
+        assert_eq!(parse_time("yesterday"), None);
+        assert_eq!(parse_time("99999999999999999"), None);
+    }
+
+    #[test]
+    fn fixed() {
+        let clock = FixedClock::from_timestamp(1111111109).unwrap();
+        assert_eq!(clock.now().timestamp(), 1111111109);
+        assert_eq!(clock.now(), clock.now());
+    }
+
+    #[test]
+    fn offset_fixed() {
+        let clock = OffsetClock::new(
+            FixedClock::from_timestamp(1234567890).unwrap(),
+            Duration::seconds(-90),
+        );
+        assert_eq!(clock.now().timestamp(), 1234567800);
+    }
+
+    #[test]
+    fn offset_system() {
+        let clock = OffsetClock::new(SystemClock, Duration::hours(1));
+        let diff = clock.now() - SystemClock.now();
+        // Allow for time passing between the 2 calls
+        assert!(diff <= Duration::hours(1) && diff > Duration::minutes(59));
+    }
+}
@@ -0,0 +1,433 @@
+// Handles encoding & decoding of the text representations used for secrets
+
+use serde::{Deserialize, Serialize};
+
+/// The text encodings a secret can be entered or exported in
+/// Secrets are always stored as raw bytes, so this only affects how they are shown to the user
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SecretEncoding {
+    #[default]
+    Base32,
+    Hex,
+    Base64,
+}
+
+impl SecretEncoding {
+    /// Decodes an entered secret into raw bytes
+    pub fn decode(&self, input: &str) -> Result<Vec<u8>, Error> {
+        match self {
+            Self::Base32 => base32::decode(input),
+            Self::Hex => hex::decode(input),
+            Self::Base64 => base64::decode(input),
+        }
+    }
+
+    /// Encodes raw bytes as canonical text
+    pub fn encode(&self, bytes: &[u8]) -> String {
+        match self {
+            Self::Base32 => base32::encode(bytes),
+            Self::Hex => hex::encode(bytes),
+            Self::Base64 => base64::encode(bytes),
+        }
+    }
+}
+
+impl std::fmt::Display for SecretEncoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Base32 => write!(f, "Base32"),
+            Self::Hex => write!(f, "Hex"),
+            Self::Base64 => write!(f, "Base64"),
+        }
+    }
+}
+
+/// Error enum to handle errors with decoding secrets
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    InvalidChar,   // A character isn't in the encoding's character set
+    InvalidLength, // The number of characters can't be a whole number of bytes
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// Decodes a Base 32 secret, so test keys can be written the way issuers give them
+#[cfg(test)]
+pub(crate) fn b32(secret: &str) -> Vec<u8> {
+    base32::decode(secret).unwrap()
+}
+
+/// RFC 4648 Base 32, as used by almost every OTP issuer
+pub mod base32 {
+    use super::Error;
+
+    /// Base 32 character set in index order, so a character's position is its 5 bit value
+    const ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+    /// Decodes a Base 32 string into bytes
+    /// Input is normalised first, so lowercase letters, whitespace, hyphens & trailing '=' padding are all accepted
+    /// Any trailing bits after the final full byte are discarded
+    pub fn decode(input: &str) -> Result<Vec<u8>, Error> {
+        let cleaned = clean(input);
+
+        // 1, 3 or 6 leftover characters can never be produced by encoding whole bytes, so a character must be missing
+        if matches!(cleaned.len() % 8, 1 | 3 | 6) {
+            return Err(Error::InvalidLength);
+        }
+
+        let mut bytes = Vec::with_capacity(cleaned.len() * 5 / 8);
+        // Bits are shifted into the buffer 5 at a time, and taken out 8 at a time
+        let mut buffer: u32 = 0;
+        let mut bits = 0;
+        for c in cleaned.bytes() {
+            let value = ALPHABET
+                .iter()
+                .position(|a| *a == c)
+                .ok_or(Error::InvalidChar)? as u32;
+            buffer = (buffer << 5) | value;
+            bits += 5;
+
+            if bits >= 8 {
+                bits -= 8;
+                bytes.push((buffer >> bits) as u8);
+                // Only keep the bits that haven't been output yet
+                buffer &= (1 << bits) - 1;
+            }
+        }
+
+        Ok(bytes)
+    }
+
+    /// Encodes bytes as canonical Base 32, which is uppercase without padding
+    pub fn encode(bytes: &[u8]) -> String {
+        let mut output = String::with_capacity((bytes.len() * 8).div_ceil(5));
+        // Bits are shifted into the buffer 8 at a time, and taken out 5 at a time
+        let mut buffer: u32 = 0;
+        let mut bits = 0;
+        for byte in bytes {
+            buffer = (buffer << 8) | *byte as u32;
+            bits += 8;
+
+            while bits >= 5 {
+                bits -= 5;
+                output.push(ALPHABET[((buffer >> bits) & 0x1F) as usize] as char);
+            }
+            buffer &= (1 << bits) - 1;
+        }
+
+        // Any remaining bits are padded with 0s to the right to form the final character
+        if bits > 0 {
+            output.push(ALPHABET[((buffer << (5 - bits)) & 0x1F) as usize] as char);
+        }
+
+        output
+    }
+
+    /// Converts a Base 32 string into its canonical form, the form secrets are stored in
+    pub fn normalise(input: &str) -> Result<String, Error> {
+        Ok(encode(&decode(input)?))
+    }
+
+    /// Removes the formatting issuers commonly add to make secrets easier to read or type
+    fn clean(input: &str) -> String {
+        let stripped: String = input
+            .chars()
+            .filter(|c| !c.is_whitespace() && *c != '-')
+            .collect();
+        stripped.trim_end_matches('=').to_ascii_uppercase()
+    }
+
+    /// Serde helper to store raw bytes as a canonical Base 32 string
+    /// Strings are decoded tolerantly, so files written before secrets were stored as bytes still load
+    pub mod as_string {
+        use serde::{Deserialize, Deserializer, Serializer};
+
+        pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_str(&super::encode(bytes))
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Vec<u8>, D::Error> {
+            let string = String::deserialize(deserializer)?;
+            super::decode(&string).map_err(serde::de::Error::custom)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        // RFC 4648 section 10 test vectors
+        const RFC_VECTORS: [(&str, &str); 7] = [
+            ("", ""),
+            ("f", "MY======"),
+            ("fo", "MZXQ===="),
+            ("foo", "MZXW6==="),
+            ("foob", "MZXW6YQ="),
+            ("fooba", "MZXW6YTB"),
+            ("foobar", "MZXW6YTBOI======"),
+        ];
+
+        #[test]
+        fn rfc4648_decode() {
+            for (plain, encoded) in RFC_VECTORS {
+                assert_eq!(decode(encoded).unwrap(), plain.as_bytes());
+            }
+        }
+
+        #[test]
+        fn rfc4648_encode() {
+            for (plain, encoded) in RFC_VECTORS {
+                assert_eq!(encode(plain.as_bytes()), encoded.trim_end_matches('='));
+            }
+        }
+
+        #[test]
+        fn tolerant() {
+            let expect = decode("JBSWY3DPEHPK3PXP").unwrap();
+            assert_eq!(decode("jbsw y3dp ehpk 3pxp").unwrap(), expect);
+            assert_eq!(decode("JBSW-Y3DP-EHPK-3PXP").unwrap(), expect);
+            assert_eq!(decode(" JBSWY3DP\tEHPK3PXP\n").unwrap(), expect);
+            assert_eq!(decode("MZXW6===").unwrap(), b"foo");
+        }
+
+        #[test]
+        fn trailing_bits() {
+            // 01100000_00011010_11100110_10000001_100
+            // The final 3 bits are discarded rather than rejected
+            assert_eq!(decode("manonam").unwrap(), vec![0x60, 0x1a, 0xe6, 0x81]);
+            assert_eq!(normalise("manonam").unwrap(), "MANONAI");
+            // 01111100_01010000_11000110_0
+            assert_eq!(decode("Primm").unwrap(), vec![0x7c, 0x50, 0xc6]);
+        }
+
+        #[test]
+        fn invalid_char() {
+            assert_eq!(decode("2082"), Err(Error::InvalidChar));
+            // Padding is only allowed at the end
+            assert_eq!(decode("MZ=XW6Y="), Err(Error::InvalidChar));
+        }
+
+        #[test]
+        fn invalid_length() {
+            assert_eq!(decode("A"), Err(Error::InvalidLength));
+            assert_eq!(decode("ACA"), Err(Error::InvalidLength));
+            assert_eq!(decode("MZXW6Y"), Err(Error::InvalidLength));
+        }
+
+        #[test]
+        fn normalise_formatted() {
+            assert_eq!(
+                normalise("jbsw y3dp ehpk 3pxp").unwrap(),
+                String::from("JBSWY3DPEHPK3PXP")
+            );
+        }
+    }
+}
+
+/// Base 16, as used by hardware token seed files
+pub mod hex {
+    use super::Error;
+
+    /// Decodes a hex string into bytes
+    /// Either case is accepted, along with an optional 0x prefix and whitespace, colon or hyphen separators
+    pub fn decode(input: &str) -> Result<Vec<u8>, Error> {
+        let trimmed = input.trim();
+        let trimmed = trimmed
+            .strip_prefix("0x")
+            .or_else(|| trimmed.strip_prefix("0X"))
+            .unwrap_or(trimmed);
+        let cleaned: Vec<char> = trimmed
+            .chars()
+            .filter(|c| !c.is_whitespace() && *c != ':' && *c != '-')
+            .collect();
+
+        // Each byte is exactly 2 characters
+        let pairs = cleaned.chunks_exact(2);
+        if !pairs.remainder().is_empty() {
+            return Err(Error::InvalidLength);
+        }
+
+        pairs
+            .map(|pair| {
+                let high = pair[0].to_digit(16).ok_or(Error::InvalidChar)?;
+                let low = pair[1].to_digit(16).ok_or(Error::InvalidChar)?;
+                Ok((high << 4 | low) as u8)
+            })
+            .collect()
+    }
+
+    /// Encodes bytes as lowercase hex
+    pub fn encode(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn round_trip() {
+            let bytes = decode("3132333435363738393031323334353637383930").unwrap();
+            assert_eq!(bytes, b"12345678901234567890");
+            assert_eq!(encode(&bytes), "3132333435363738393031323334353637383930");
+        }
+
+        #[test]
+        fn tolerant() {
+            let expect = vec![0xde, 0xad, 0xbe, 0xef];
+            assert_eq!(decode("DEADBEEF").unwrap(), expect);
+            assert_eq!(decode("0xdeadbeef").unwrap(), expect);
+            assert_eq!(decode("de:ad:be:ef").unwrap(), expect);
+            assert_eq!(decode(" de ad-be ef ").unwrap(), expect);
+        }
+
+        #[test]
+        fn invalid() {
+            assert_eq!(decode("abc"), Err(Error::InvalidLength));
+            assert_eq!(decode("abcg"), Err(Error::InvalidChar));
+        }
+    }
+}
+
+/// RFC 4648 Base 64, as used by some admin consoles & authenticator exports
+pub mod base64 {
+    use super::Error;
+
+    /// Base 64 character set in index order, so a character's position is its 6 bit value
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    /// Decodes a Base 64 string into bytes
+    /// Both the standard & URL safe alphabets are accepted, with or without '=' padding, ignoring whitespace
+    pub fn decode(input: &str) -> Result<Vec<u8>, Error> {
+        let cleaned: Vec<u8> = input.bytes().filter(|c| !c.is_ascii_whitespace()).collect();
+        // Padding is only allowed at the end
+        let end = cleaned
+            .iter()
+            .rposition(|c| *c != b'=')
+            .map_or(0, |i| i + 1);
+        let cleaned = &cleaned[..end];
+
+        // A single leftover character can never be produced by encoding whole bytes
+        if cleaned.len() % 4 == 1 {
+            return Err(Error::InvalidLength);
+        }
+
+        let mut bytes = Vec::with_capacity(cleaned.len() * 3 / 4);
+        // Bits are shifted into the buffer 6 at a time, and taken out 8 at a time
+        let mut buffer: u32 = 0;
+        let mut bits = 0;
+        for c in cleaned {
+            // URL safe characters are mapped onto their standard equivalents
+            let c = match c {
+                b'-' => b'+',
+                b'_' => b'/',
+                c => *c,
+            };
+            let value = ALPHABET
+                .iter()
+                .position(|a| *a == c)
+                .ok_or(Error::InvalidChar)? as u32;
+            buffer = (buffer << 6) | value;
+            bits += 6;
+
+            if bits >= 8 {
+                bits -= 8;
+                bytes.push((buffer >> bits) as u8);
+                buffer &= (1 << bits) - 1;
+            }
+        }
+
+        Ok(bytes)
+    }
+
+    /// Encodes bytes as standard Base 64, with padding
+    pub fn encode(bytes: &[u8]) -> String {
+        let mut output = String::with_capacity(bytes.len().div_ceil(3) * 4);
+        for chunk in bytes.chunks(3) {
+            // Pack up to 3 bytes into 24 bits, then take them out 6 at a time
+            let buffer = chunk
+                .iter()
+                .enumerate()
+                .fold(0u32, |acc, (i, b)| acc | (*b as u32) << (16 - 8 * i));
+            for i in 0..4 {
+                // A chunk of n bytes needs n + 1 characters, with the rest being padding
+                if i <= chunk.len() {
+                    output.push(ALPHABET[((buffer >> (18 - 6 * i)) & 0x3F) as usize] as char);
+                } else {
+                    output.push('=');
+                }
+            }
+        }
+        output
+    }
+
+    /// Serde helper to store raw bytes as a standard Base 64 string
+    pub mod as_string {
+        use serde::{Deserialize, Deserializer, Serializer};
+
+        pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_str(&super::encode(bytes))
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Vec<u8>, D::Error> {
+            let string = String::deserialize(deserializer)?;
+            super::decode(&string).map_err(serde::de::Error::custom)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        // RFC 4648 section 10 test vectors
+        const RFC_VECTORS: [(&str, &str); 7] = [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ];
+
+        #[test]
+        fn rfc4648_decode() {
+            for (plain, encoded) in RFC_VECTORS {
+                assert_eq!(decode(encoded).unwrap(), plain.as_bytes());
+                assert_eq!(
+                    decode(encoded.trim_end_matches('=')).unwrap(),
+                    plain.as_bytes()
+                );
+            }
+        }
+
+        #[test]
+        fn rfc4648_encode() {
+            for (plain, encoded) in RFC_VECTORS {
+                assert_eq!(encode(plain.as_bytes()), encoded);
+            }
+        }
+
+        #[test]
+        fn url_safe() {
+            assert_eq!(decode("-_8=").unwrap(), decode("+/8=").unwrap());
+        }
+
+        #[test]
+        fn invalid() {
+            assert_eq!(decode("Zm9vY"), Err(Error::InvalidLength));
+            assert_eq!(decode("Zm=9vY"), Err(Error::InvalidChar));
+            assert_eq!(decode("Zm9*"), Err(Error::InvalidChar));
+        }
+    }
+}
@@ -0,0 +1,216 @@
+// Handles the errors that can be returned by the library's public functions
+
+use crate::encoding;
+use crate::file::aegis;
+use crate::migration;
+use crate::qr;
+use crate::uri;
+
+/// Error enum for every error the library can return
+#[derive(Debug)]
+pub enum Error {
+    Validation(ValidationError), // A key can't be used to generate codes
+    NotFound(String),            // No stored key has the name requested
+    UnknownId(u64), // No stored key has the ID requested, such as when it has since been removed
+    Duplicate,      // A key with the same secret is already stored
+    Io(std::io::Error), // A file couldn't be read or written
+    Decryption, // Data couldn't be decrypted, as the password is incorrect or the file is corrupt
+    Corrupt,    // File isn't in a format that can be read, as it is truncated or damaged
+    UnsupportedVersion, // File was saved by a newer version of the app
+    NoBackup,   // No backup of the key file could be read
+    NoKeyFile,  // Key file doesn't exist, where one is needed rather than created
+    Parse(ParseError), // Data couldn't be parsed into the expected structure
+}
+
+/// Reasons a key can fail validation
+#[derive(Debug, PartialEq, Eq)]
+pub enum ValidationError {
+    EmptyName,               // Name is empty
+    CodeLength,              // Code length is outside of LENGTH_RANGE
+    Interval,                // Time interval is 0
+    EmptySecret,             // Secret has no bytes
+    Secret(encoding::Error), // Secret text couldn't be decoded
+}
+
+/// Sources of data that can fail to parse
+#[derive(Debug)]
+pub enum ParseError {
+    Json(serde_json::Error), // Stored data isn't valid JSON for the expected type
+    Qr(qr::Error),           // A QR code couldn't be read from an image
+    Uri(uri::Error),         // A key URI is invalid
+    Migration(migration::Error), // A Google Authenticator export is invalid
+    Aegis(aegis::Error),     // An Aegis export is invalid
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Parse(ParseError::Json(e)) => Some(e),
+            Self::Parse(ParseError::Qr(e)) => Some(e),
+            Self::Parse(ParseError::Uri(e)) => Some(e),
+            Self::Parse(ParseError::Migration(e)) => Some(e),
+            Self::Parse(ParseError::Aegis(e)) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+// Messages are written to be shown directly to the user, as are those of the module errors it wraps that have their own Display
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Validation(e) => write!(f, "{}", e),
+            Self::NotFound(name) => write!(f, "No key named {} was found", name),
+            Self::UnknownId(_) => write!(f, "Key no longer exists"),
+            Self::Duplicate => write!(f, "Key has already been added"),
+            Self::Io(e) => write!(f, "Could not access file: {}", e),
+            Self::Decryption => write!(f, "Incorrect password"),
+            Self::Corrupt => write!(f, "Key file is corrupt"),
+            Self::UnsupportedVersion => {
+                write!(f, "Key file was saved by a newer version of Authogonal")
+            }
+            Self::NoBackup => write!(f, "No readable backup was found"),
+            Self::NoKeyFile => write!(f, "No key file found"),
+            Self::Parse(ParseError::Json(_)) => write!(f, "Stored data is corrupt"),
+            Self::Parse(ParseError::Qr(qr::Error::Read)) => write!(f, "Could not read QR code"),
+            Self::Parse(ParseError::Qr(qr::Error::Clipboard)) => {
+                write!(f, "Could not access the clipboard")
+            }
+            Self::Parse(ParseError::Qr(qr::Error::NoImage)) => {
+                write!(f, "The clipboard doesn't contain an image")
+            }
+            Self::Parse(ParseError::Uri(e)) => write!(f, "{}", e),
+            Self::Parse(ParseError::Migration(e)) => write!(f, "{}", e),
+            Self::Parse(ParseError::Aegis(e)) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::EmptyName => write!(f, "Name cannot be empty"),
+            Self::CodeLength => write!(f, "Invalid code length"),
+            Self::Interval => write!(f, "Invalid interval"),
+            Self::EmptySecret | Self::Secret(encoding::Error::InvalidLength) => {
+                write!(f, "Invalid secret length")
+            }
+            Self::Secret(encoding::Error::InvalidChar) => {
+                write!(f, "Invalid character in secret")
+            }
+        }
+    }
+}
+
+impl From<ValidationError> for Error {
+    fn from(e: ValidationError) -> Self {
+        Self::Validation(e)
+    }
+}
+
+impl From<encoding::Error> for Error {
+    fn from(e: encoding::Error) -> Self {
+        Self::Validation(ValidationError::Secret(e))
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Parse(ParseError::Json(e))
+    }
+}
+
+impl From<qr::Error> for Error {
+    fn from(e: qr::Error) -> Self {
+        Self::Parse(ParseError::Qr(e))
+    }
+}
+
+impl From<uri::Error> for Error {
+    fn from(e: uri::Error) -> Self {
+        Self::Parse(ParseError::Uri(e))
+    }
+}
+
+impl From<migration::Error> for Error {
+    fn from(e: migration::Error) -> Self {
+        Self::Parse(ParseError::Migration(e))
+    }
+}
+
+impl From<aegis::Error> for Error {
+    fn from(e: aegis::Error) -> Self {
+        Self::Parse(ParseError::Aegis(e))
+    }
+}
+
+impl From<encrypt::Error> for Error {
+    fn from(e: encrypt::Error) -> Self {
+        match e {
+            encrypt::Error::WriteError => Self::Io(std::io::Error::other("Could not encrypt data")),
+            encrypt::Error::FormatError => Self::Corrupt,
+            encrypt::Error::VersionError => Self::UnsupportedVersion,
+            // Any other error means the data couldn't be decrypted
+            _ => Self::Decryption,
+        }
+    }
+}
+
+impl From<Box<dyn std::error::Error>> for Error {
+    // The encrypt crate returns boxed errors, so the concrete type is recovered with a downcast
+    fn from(e: Box<dyn std::error::Error>) -> Self {
+        let e = match e.downcast::<std::io::Error>() {
+            Ok(io) => return Self::Io(*io),
+            Err(e) => e,
+        };
+        match e.downcast::<encrypt::Error>() {
+            Ok(e) => Self::from(*e),
+            // Any other error means the data was decrypted, but isn't valid text
+            Err(_) => Self::Corrupt,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encrypt_io() {
+        let boxed: Box<dyn std::error::Error> =
+            Box::new(std::io::Error::from(std::io::ErrorKind::NotFound));
+        assert!(matches!(Error::from(boxed), Error::Io(_)));
+    }
+
+    #[test]
+    fn encrypt_decryption() {
+        let boxed: Box<dyn std::error::Error> = Box::new(encrypt::Error::ReadError);
+        assert!(matches!(Error::from(boxed), Error::Decryption));
+    }
+
+    #[test]
+    fn encrypt_version() {
+        let boxed: Box<dyn std::error::Error> = Box::new(encrypt::Error::VersionError);
+        assert!(matches!(Error::from(boxed), Error::UnsupportedVersion));
+    }
+
+    #[test]
+    fn encrypt_invalid_text() {
+        let boxed: Box<dyn std::error::Error> =
+            Box::new(String::from_utf8(vec![0xff]).unwrap_err());
+        assert!(matches!(Error::from(boxed), Error::Corrupt));
+    }
+
+    #[test]
+    fn secret_message() {
+        let e = Error::from(encoding::Error::InvalidChar);
+        assert_eq!(e.to_string(), "Invalid character in secret");
+    }
+}
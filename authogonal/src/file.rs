@@ -1,100 +1,1139 @@
 // Handles interface with reading and writing keys to encrypted files
 
+use crate::error::Error;
 use std::fs::File;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
 /// Filename for key file
 pub const KEYPATH: &str = "keys";
 /// Filename for settings file
 pub const SETTINGSPATH: &str = "settings.json";
+/// Environment variable that sets the directory all files are stored in
+pub const DATA_DIR_VAR: &str = "AUTHOGONAL_DATA_DIR";
+/// Filename that, when a file with this name is beside the executable, keeps all files beside the executable (portable mode)
+pub const PORTABLE_MARKER: &str = "portable";
+/// Name of the app's own directory within the platform's data & config directories
+const APP_DIR: &str = "authogonal";
 
-pub fn get_dir() -> std::path::PathBuf {
-    let exe_path = std::env::current_exe().unwrap();
-    exe_path.parent().unwrap().to_path_buf()
+/// Directory chosen on the command line, which takes priority over any other
+static DIR_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+/// Directories once resolved, so files are only ever migrated once
+static DIRS: OnceLock<Dirs> = OnceLock::new();
+
+/// Directories that the key & settings files are stored in
+#[derive(Clone, Debug, PartialEq)]
+struct Dirs {
+    data: PathBuf,   // Contains the key file & its backups
+    config: PathBuf, // Contains the settings file
+    migrate: bool,   // Whether files left in the legacy location should be moved here
+}
+
+/// Sets the directory all files are stored in, overriding the environment variable & the default directories
+/// Must be called before any files are accessed, later calls have no effect
+pub fn set_data_dir(dir: PathBuf) {
+    let _ = DIR_OVERRIDE.set(dir);
+}
+
+/// Directory that the key file is stored in
+pub fn data_dir() -> Result<PathBuf, Error> {
+    Ok(resolve_dirs()?.data)
+}
+
+/// Directory that the settings file is stored in
+pub fn config_dir() -> Result<PathBuf, Error> {
+    Ok(resolve_dirs()?.config)
+}
+
+/// Directory containing the executable, which is where files were stored before the data directory could be configured
+fn exe_dir() -> Result<PathBuf, Error> {
+    let exe_path = std::env::current_exe()?;
+    let dir = exe_path
+        .parent()
+        .ok_or(std::io::Error::from(std::io::ErrorKind::NotFound))?;
+    Ok(dir.to_path_buf())
+}
+
+/// Resolves the directories on first use, creating them and migrating any files from the legacy location
+fn resolve_dirs() -> Result<Dirs, Error> {
+    if let Some(dirs) = DIRS.get() {
+        return Ok(dirs.clone());
+    }
+
+    let exe_dir = exe_dir()?;
+    let platform = dirs::data_dir()
+        .zip(dirs::config_dir())
+        .map(|(data, config)| (data.join(APP_DIR), config.join(APP_DIR)));
+    let dirs = choose_dirs(
+        DIR_OVERRIDE.get().cloned(),
+        std::env::var_os(DATA_DIR_VAR).map(PathBuf::from),
+        &exe_dir,
+        platform,
+    );
+
+    std::fs::create_dir_all(&dirs.data)?;
+    std::fs::create_dir_all(&dirs.config)?;
+    if dirs.migrate {
+        migrate(&exe_dir, &dirs)?;
+    }
+
+    Ok(DIRS.get_or_init(|| dirs).clone())
+}
+
+/// Chooses the directories to use, in order of priority:
+/// the command line, the environment variable, beside the executable in portable mode, then the platform's directories
+/// If the platform has no directories, files are kept beside the executable
+fn choose_dirs(
+    flag: Option<PathBuf>,
+    env: Option<PathBuf>,
+    exe_dir: &Path,
+    platform: Option<(PathBuf, PathBuf)>,
+) -> Dirs {
+    // A single chosen directory holds both files
+    let single = |dir: PathBuf| Dirs {
+        data: dir.clone(),
+        config: dir,
+        migrate: false,
+    };
+
+    if let Some(dir) = flag {
+        return single(dir);
+    }
+    // An empty variable is treated as unset
+    if let Some(dir) = env.filter(|d| !d.as_os_str().is_empty()) {
+        return single(dir);
+    }
+    if exe_dir.join(PORTABLE_MARKER).exists() {
+        return single(exe_dir.to_path_buf());
+    }
+    match platform {
+        Some((data, config)) => Dirs {
+            data,
+            config,
+            migrate: true,
+        },
+        None => single(exe_dir.to_path_buf()),
+    }
+}
+
+/// Moves the key file, its backups & the settings file from the legacy directory, if they haven't been created in the new directories yet
+fn migrate(legacy: &Path, dirs: &Dirs) -> Result<(), Error> {
+    let keys = legacy.join(KEYPATH);
+    if keys.exists() && !dirs.data.join(KEYPATH).exists() {
+        // Backups are moved before the key file, so an interrupted migration is attempted again next time
+        for backup in keys::backup_paths(&keys) {
+            if let Some(name) = backup.file_name() {
+                if backup.exists() {
+                    move_file(&backup, &dirs.data.join(name))?;
+                }
+            }
+        }
+        move_file(&keys, &dirs.data.join(KEYPATH))?;
+    }
+
+    let settings = legacy.join(SETTINGSPATH);
+    if settings.exists() && !dirs.config.join(SETTINGSPATH).exists() {
+        move_file(&settings, &dirs.config.join(SETTINGSPATH))?;
+    }
+
+    Ok(())
+}
+
+/// Moves a file, copying it if it can't be renamed (such as when moving between drives)
+/// If the original can't be removed (such as when it is read-only), it is left in place
+fn move_file(from: &Path, to: &Path) -> Result<(), Error> {
+    if std::fs::rename(from, to).is_err() {
+        std::fs::copy(from, to)?;
+        let _ = std::fs::remove_file(from);
+    }
+    Ok(())
 }
 
 /// Handles operations with the key file
 /// Data stored in the key file is encrypted, so all functions require an [EncryptionKey]
 pub mod keys {
     use super::*;
-    use crate::key::Key;
+    use crate::key::{CodeOptions, Key};
     use encrypt::EncryptionKey;
 
-    /// Appends a key to the key file, returning an error String if key is invalid, or a key with the same name attribute already exists
-    pub fn add(key: &Key, e_key: &EncryptionKey) -> Result<(), String> {
-        // Check that key to add is valid
-        key.validate()?;
+    /// Number of previous versions of the key file kept as backups
+    pub const BACKUPS: usize = 3;
+
+    /// Appends a key to the key file, returning an error if key is invalid or its secret is already stored
+    /// The key is given a new ID, so on success, returns the key as it was stored
+    pub fn add(key: &Key, e_key: &EncryptionKey) -> Result<Key, Error> {
+        add_at(&key_path()?, key, e_key)
+    }
+
+    fn add_at(path: &Path, key: &Key, e_key: &EncryptionKey) -> Result<Key, Error> {
+        // Load already existing keys to find an unused ID, and to allow append to the end using vector methods
+        // If the file can't be read, nothing is written, so the stored keys aren't overwritten
+        let mut load = read_path(path, e_key)?;
+
+        // Validated & checked for duplicates in the same way as imported keys
+        // Names don't need to be unique, as keys are identified by their ID
+        let key = append(&mut load, std::slice::from_ref(key)).remove(0)?;
+        save_at(path, &load, e_key)?;
+        Ok(key)
+    }
 
-        // Load already existing keys to check for name matches, and to allow append to the end using vector methods
-        let mut load = load(e_key);
+    /// Appends several keys to the key file at once, such as when importing, so the file is only saved (and backed up) once
+    /// Each key is validated separately, and keys with the same secret as one already stored are rejected as duplicates
+    /// Returns the stored key or the reason it wasn't added for each key, in the order they were given
+    pub fn import(keys: &[Key], e_key: &EncryptionKey) -> Result<Vec<Result<Key, Error>>, Error> {
+        let path = key_path()?;
+        // If the file can't be read, nothing is written, so the stored keys aren't overwritten
+        let mut load = read_path(&path, e_key)?;
+        let results = append(&mut load, keys);
 
-        // Validation, returning an error if key with name already exists (as needs to be unique to index into HashMap)
-        // Operation is performed here as all keys are accessible to be checked
-        if let None = load.iter().find(|k| *k.name == key.name) {
-            // Add to end of vector and save again
-            load.push(key.clone());
-            save(&load, e_key).unwrap();
-            Ok(())
-        } else {
-            Err(String::from("A key with that name already exists"))
+        // Nothing to save if every key was rejected
+        if results.iter().any(Result::is_ok) {
+            save_at(&path, &load, e_key)?;
         }
+        Ok(results)
+    }
+
+    /// Appends each valid key that isn't a duplicate to the list of stored keys, giving it a new ID
+    fn append(stored: &mut Vec<Key>, keys: &[Key]) -> Vec<Result<Key, Error>> {
+        let mut results = Vec::new();
+        for key in keys {
+            if let Err(e) = key.validate() {
+                results.push(Err(e));
+                continue;
+            }
+            // Compared against keys added earlier in the import too
+            if stored.iter().any(|k| k.secret == key.secret) {
+                results.push(Err(Error::Duplicate));
+                continue;
+            }
+
+            let mut key = key.clone();
+            key.id = next_id(stored);
+            stored.push(key.clone());
+            results.push(Ok(key));
+        }
+        results
+    }
+
+    /// Removes key with a given ID from the key file, returning an error if no key has that ID
+    pub fn remove(id: u64, e_key: &EncryptionKey) -> Result<(), Error> {
+        remove_at(&key_path()?, id, e_key)
     }
 
-    /// Removes key with a given name from the key file
-    pub fn remove(key_name: &String, e_key: &EncryptionKey) {
-        let mut load = load(e_key);
+    fn remove_at(path: &Path, id: u64, e_key: &EncryptionKey) -> Result<(), Error> {
+        let mut load = read_path(path, e_key)?;
         // Removes specified key from loaded list and saves it back to file
-        load.remove(
-            load.iter()
-                .position(|k| &k.name == key_name)
-                .expect("Key not found"), // Panics if key name invalid
-        );
-        save(&load, e_key).unwrap();
+        let index = position(&load, id)?;
+        load.remove(index);
+        save_at(path, &load, e_key)
+    }
+
+    /// Changes the issuer, account name & options of the key with a given ID, keeping its secret
+    /// As the HOTP counter is part of the options, this also sets the counter
+    /// On success, returns the key as it was stored
+    pub fn update(
+        id: u64,
+        issuer: String,
+        account: String,
+        options: CodeOptions,
+        e_key: &EncryptionKey,
+    ) -> Result<Key, Error> {
+        update_at(&key_path()?, id, issuer, account, options, e_key)
+    }
+
+    fn update_at(
+        path: &Path,
+        id: u64,
+        issuer: String,
+        account: String,
+        options: CodeOptions,
+        e_key: &EncryptionKey,
+    ) -> Result<Key, Error> {
+        let mut load = read_path(path, e_key)?;
+        let index = position(&load, id)?;
+
+        let mut key = load[index].clone();
+        key.issuer = issuer;
+        key.account = account;
+        key.options = options;
+        key.validate()?;
+
+        load[index] = key.clone();
+        save_at(path, &load, e_key)?;
+        Ok(key)
+    }
+
+    /// Index of the key with a given ID
+    fn position(keys: &[Key], id: u64) -> Result<usize, Error> {
+        keys.iter()
+            .position(|k| k.id == id)
+            .ok_or(Error::UnknownId(id))
+    }
+
+    /// An ID not used by any of the keys, which is always greater than all of them so IDs are never reused while their key is stored
+    fn next_id(keys: &[Key]) -> u64 {
+        keys.iter().map(|k| k.id).max().unwrap_or(0) + 1
+    }
+
+    /// Gives an ID to keys that are missing one (as they were saved before IDs existed) or share one with an earlier key (as the file was edited by hand)
+    /// IDs are assigned in order, so the same file is always given the same IDs, and they're saved the next time the file is
+    fn assign_ids(keys: &mut [Key]) {
+        let mut next = next_id(keys);
+        for i in 0..keys.len() {
+            let id = keys[i].id;
+            if id == 0 || keys[..i].iter().any(|k| k.id == id) {
+                keys[i].id = next;
+                next += 1;
+            }
+        }
+    }
+
+    /// Path of the key file within the data directory
+    /// Functions that take a path act on the key file at that path, so they can be tested without the data directory
+    fn key_path() -> Result<PathBuf, Error> {
+        Ok(data_dir()?.join(Path::new(KEYPATH)))
+    }
+
+    /// Save function to write a vec of keys to a key file
+    /// The previous version of the file is kept as a backup
+    fn save_at(path: &Path, keys: &Vec<Key>, e_key: &EncryptionKey) -> Result<(), Error> {
+        backup(path)?;
+        write_at(path, keys, e_key)
     }
 
-    /// Save function to write a vec of keys to file
-    fn save(keys: &Vec<Key>, e_key: &EncryptionKey) -> Result<(), Box<dyn std::error::Error>> {
-        // Create path from key path constant
-        let path = get_dir().join(Path::new(KEYPATH));
+    /// Writes a vec of keys to a key file without backing it up first
+    fn write_at(path: &Path, keys: &Vec<Key>, e_key: &EncryptionKey) -> Result<(), Error> {
         // Convert keys to JSON format
-        let message = serde_json::to_string(&keys).unwrap();
+        let message = serde_json::to_string(&keys)?;
         // Save using encrypt external module
-        encrypt::save(&path, e_key, message)
+        Ok(encrypt::save(path, e_key, message)?)
     }
 
     /// Load data from key file
-    pub fn load(e_key: &EncryptionKey) -> Vec<Key> {
-        let path = get_dir().join(Path::new(KEYPATH));
-        if let Ok(m) = encrypt::load(&path, e_key) {
-            if let Ok(v) = serde_json::from_str(&m) {
-                // If decryption succeeds and data is valid JSON, return
-                return v;
+    /// A missing or empty file has no keys, whereas a file that can't be decrypted or parsed returns an error
+    pub fn load(e_key: &EncryptionKey) -> Result<Vec<Key>, Error> {
+        read_path(&key_path()?, e_key)
+    }
+
+    /// Writes the decrypted contents of the key file to a path as plain text, so it can be recovered by hand if it can't be parsed
+    pub fn export_raw(e_key: &EncryptionKey, path: &Path) -> Result<(), Error> {
+        let message = encrypt::load(&key_path()?, e_key)?;
+        Ok(std::fs::write(path, message)?)
+    }
+
+    /// Reads the keys from a key file at any path, such as a backup
+    fn read_path(path: &Path, e_key: &EncryptionKey) -> Result<Vec<Key>, Error> {
+        let message = encrypt::load(path, e_key)?;
+        if message.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut keys: Vec<Key> = serde_json::from_str(&message)?;
+        assign_ids(&mut keys);
+        Ok(keys)
+    }
+
+    /// Paths of the existing backups of the key file, newest first
+    pub fn backups() -> Result<Vec<PathBuf>, Error> {
+        Ok(backups_at(&key_path()?))
+    }
+
+    fn backups_at(path: &Path) -> Vec<PathBuf> {
+        backup_paths(path)
+            .into_iter()
+            .filter(|p| p.exists())
+            .collect()
+    }
+
+    /// Replaces the key file with the newest backup that can be read, returning its keys
+    pub fn restore_latest(e_key: &EncryptionKey) -> Result<Vec<Key>, Error> {
+        restore_latest_at(&key_path()?, e_key)
+    }
+
+    fn restore_latest_at(path: &Path, e_key: &EncryptionKey) -> Result<Vec<Key>, Error> {
+        for backup in backups_at(path) {
+            if let Ok(keys) = read_path(&backup, e_key) {
+                // The unreadable file becomes the newest backup, rather than being lost
+                save_at(path, &keys, e_key)?;
+                return Ok(keys);
+            }
+        }
+        Err(Error::NoBackup)
+    }
+
+    /// Path of each backup of a file, in order of age
+    pub(super) fn backup_paths(path: &Path) -> Vec<PathBuf> {
+        (1..=BACKUPS)
+            .map(|i| {
+                let mut backup = path.as_os_str().to_owned();
+                backup.push(format!(".bak{i}"));
+                PathBuf::from(backup)
+            })
+            .collect()
+    }
+
+    /// Copies a file to its newest backup, moving the other backups back one place and discarding the oldest
+    fn backup(path: &Path) -> Result<(), Error> {
+        if !path.try_exists()? {
+            return Ok(());
+        }
+
+        let backups = backup_paths(path);
+        // Oldest first, so no backup is overwritten before it's moved
+        for pair in backups.windows(2).rev() {
+            if pair[0].try_exists()? {
+                std::fs::rename(&pair[0], &pair[1])?;
             }
         }
+        std::fs::copy(path, &backups[0])?;
 
-        // If couldn't retrieve data, return an empty vector
-        Vec::new()
+        Ok(())
     }
 
     /// Increment a specified key's HOTP counter by 1 and save to key file
-    pub fn save_increment(
-        key: &Key,
-        e_key: &EncryptionKey,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let mut keys = load(e_key);
+    pub fn save_increment(key: &Key, e_key: &EncryptionKey) -> Result<(), Error> {
+        save_increment_at(&key_path()?, key, e_key)
+    }
+
+    fn save_increment_at(path: &Path, key: &Key, e_key: &EncryptionKey) -> Result<(), Error> {
+        let mut keys = read_path(path, e_key)?;
         // Find key in file to increment
-        if let Some(k) = keys.iter_mut().find(|k| *k == key) {
-            // Deref and increment counter value, then save
-            (*k).options.method.increment_counter();
-            return save(&keys, e_key);
+        let index = position(&keys, key.id)?;
+        // Increment counter value, then save
+        keys[index].options.method.increment_counter();
+        // Not backed up, as a few increments would otherwise replace every backup of the keys themselves
+        write_at(path, &keys, e_key)
+    }
+
+    /// Derives the [EncryptionKey] for the key file from a password, checking it can decrypt the file
+    /// If the key file doesn't exist yet, an empty one is created using a new key
+    /// Files saved before keys were salted are re-encrypted with a new salted key, which is returned in place of the legacy one
+    pub fn unlock(password: &str) -> Result<EncryptionKey, Error> {
+        unlock_at(&key_path()?, password)
+    }
+
+    /// Derives the [EncryptionKey] for the key file from a password, without creating the file if it doesn't exist
+    /// Used where a missing file is more likely to be a mistake, such as a mistyped directory, than a first run
+    pub fn unlock_existing(password: &str) -> Result<EncryptionKey, Error> {
+        unlock_existing_at(&key_path()?, password)
+    }
+
+    fn unlock_existing_at(path: &Path, password: &str) -> Result<EncryptionKey, Error> {
+        if !path.try_exists()? {
+            return Err(Error::NoKeyFile);
+        }
+        unlock_at(path, password)
+    }
+
+    fn unlock_at(path: &Path, password: &str) -> Result<EncryptionKey, Error> {
+        // If the keys file doesn't exist, create it
+        if !path.try_exists()? {
+            let e_key = EncryptionKey::new(password)?;
+            save_at(path, &Vec::new(), &e_key)?;
+            return Ok(e_key);
+        }
+
+        // If the key file is too damaged to be decrypted, a key is instead derived from a backup, so the backup can be restored once unlocked
+        let e_key = match EncryptionKey::from_file(password, path).map_err(Error::from) {
+            Err(Error::Corrupt) => return unlock_backup(path, password),
+            e_key => e_key?,
+        };
+        let message = match encrypt::load(path, &e_key).map_err(Error::from) {
+            Err(Error::Corrupt) => return unlock_backup(path, password),
+            message => message?,
+        };
+
+        // Migrate legacy files now that the password is known to be correct
+        // The legacy file is backed up first, and its backups are migrated with it so they can still be restored
+        if e_key.is_legacy() {
+            let new_key = EncryptionKey::new(password)?;
+            backup(path)?;
+            rekey_at(path, &e_key, &new_key)?;
+            return Ok(new_key);
+        }
+        // Files saved in an older format are saved again with the same key, so they're written in the current format
+        if encrypt::needs_upgrade(path)? {
+            encrypt::save(path, &e_key, message)?;
+        }
+        // Completes a password change that was interrupted
+        finish_rekey(path, &e_key)?;
+
+        Ok(e_key)
+    }
+
+    /// Derives the [EncryptionKey] from the newest backup that the password can decrypt
+    /// If none can be decrypted, the error from the newest backup is returned
+    fn unlock_backup(path: &Path, password: &str) -> Result<EncryptionKey, Error> {
+        let mut error = Error::Corrupt;
+        for (i, path) in backups_at(path).into_iter().enumerate() {
+            let e_key = EncryptionKey::from_file(password, &path)
+                .and_then(|e_key| encrypt::load(&path, &e_key).map(|_| e_key));
+            match e_key {
+                // Legacy keys aren't used for writing, so backups that need one are skipped
+                Ok(e_key) if !e_key.is_legacy() => return Ok(e_key),
+                Err(e) if i == 0 => error = e.into(),
+                _ => (),
+            }
+        }
+        Err(error)
+    }
+
+    /// Changes the password of the key file, keeping all stored keys
+    /// The current password is checked first, then the file is re-encrypted with a key derived from the new password, which is returned
+    pub fn change_password(password: &str, new_password: &str) -> Result<EncryptionKey, Error> {
+        change_password_at(&key_path()?, password, EncryptionKey::new(new_password)?)
+    }
+
+    /// Re-encrypts the key file & its backups with a new key, once the current password has been checked
+    fn change_password_at(
+        path: &Path,
+        password: &str,
+        new_key: EncryptionKey,
+    ) -> Result<EncryptionKey, Error> {
+        let e_key = EncryptionKey::from_file(password, path)?;
+        // Fails without changing any file if the current password is incorrect
+        encrypt::load(path, &e_key)?;
+        rekey_at(path, &e_key, &new_key)?;
+        Ok(new_key)
+    }
+
+    /// Re-encrypts the key file & its backups with a new key, after decrypting them with the current key
+    fn rekey_at(path: &Path, e_key: &EncryptionKey, new_key: &EncryptionKey) -> Result<(), Error> {
+        // Backups are re-encrypted too, so they can still be restored
+        // Each is written to a staged copy first, so if any fails, every file is left as it was
+        // Backups that couldn't be read before are left as they are
+        let mut staged = Vec::new();
+        for backup in backups_at(path) {
+            let Ok(message) = encrypt::load(&backup, e_key) else {
+                continue;
+            };
+            staged.push(staged_path(&backup));
+            if let Err(e) = encrypt::save(&staged_path(&backup), new_key, message) {
+                discard(&staged);
+                return Err(e.into());
+            }
+        }
+        if let Err(e) = encrypt::rekey(path, e_key, new_key) {
+            discard(&staged);
+            return Err(e.into());
+        }
+
+        // The key file now uses the new key, so the staged copies can replace the backups
+        finish_rekey(path, new_key)
+    }
+
+    /// Path that a backup's re-encrypted copy is written to while the password is being changed
+    fn staged_path(backup: &Path) -> PathBuf {
+        let mut staged = backup.as_os_str().to_owned();
+        staged.push(".new");
+        PathBuf::from(staged)
+    }
+
+    /// Removes the staged copies of a password change that failed
+    /// The backups themselves haven't been changed, so a copy that can't be removed is only tidied up later by [finish_rekey]
+    fn discard(staged: &[PathBuf]) {
+        for path in staged {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    /// Moves the staged copies of a password change over the backups they were made from
+    /// If the change was interrupted before the key file was re-encrypted, the copies can't be read with its key, so are removed instead
+    fn finish_rekey(path: &Path, e_key: &EncryptionKey) -> Result<(), Error> {
+        for backup in backup_paths(path) {
+            let staged = staged_path(&backup);
+            if !staged.try_exists()? {
+                continue;
+            }
+            if encrypt::load(&staged, e_key).is_ok() {
+                std::fs::rename(&staged, &backup)?;
+            } else {
+                std::fs::remove_file(&staged)?;
+            }
         }
         Ok(())
     }
 
     /// Replaces the current key file with an empty one
-    pub fn new_file(e_key: &EncryptionKey) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn new_file(e_key: &EncryptionKey) -> Result<(), Error> {
         // Writes an empty vec to the file, overwriting existing data
-        save(&Vec::new(), e_key)
+        save_at(&key_path()?, &Vec::new(), e_key)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::otp::OTPMethod;
+
+        #[test]
+        fn backup_rotation() {
+            let path = temp_path("authogonal_backup_rotation");
+
+            // Nothing to back up
+            backup(&path).unwrap();
+            assert!(!backup_paths(&path)[0].exists());
+
+            for i in 0..=BACKUPS {
+                std::fs::write(&path, i.to_string()).unwrap();
+                backup(&path).unwrap();
+            }
+            let contents: Vec<String> = backup_paths(&path)
+                .iter()
+                .map(|p| std::fs::read_to_string(p).unwrap())
+                .collect();
+            let _ = std::fs::remove_dir_all(path.parent().unwrap());
+
+            // Newest first, with the oldest discarded
+            assert_eq!(contents, ["3", "2", "1"]);
+        }
+
+        #[test]
+        fn import_keys() {
+            let key = |secret: u8, account: &str| {
+                Key::new(
+                    vec![secret],
+                    String::new(),
+                    account.to_string(),
+                    Default::default(),
+                )
+            };
+            let mut stored = vec![Key {
+                id: 4,
+                ..key(1, "a")
+            }];
+
+            let results = append(
+                &mut stored,
+                &[key(1, "b"), key(2, ""), key(3, "c"), key(3, "d")],
+            );
+
+            assert!(matches!(results[0], Err(Error::Duplicate)));
+            assert!(matches!(results[1], Err(Error::Validation(_))));
+            assert_eq!(results[2].as_ref().unwrap().id, 5);
+            // Duplicates within the import are also rejected
+            assert!(matches!(results[3], Err(Error::Duplicate)));
+            assert_eq!(stored.len(), 2);
+        }
+
+        /// Creates an empty directory for a test, returning the key file's path within it
+        fn temp_path(name: &str) -> PathBuf {
+            crate::file::tests::temp_dir(name).join(KEYPATH)
+        }
+
+        #[test]
+        fn update_key() {
+            let path = temp_path("authogonal_update_key");
+            let params = encrypt::KdfParams::TEST;
+            let e_key = EncryptionKey::with_params("2082", params).unwrap();
+            let hotp = CodeOptions::new_or_default(Some(OTPMethod::HOTP(0)), None, None, None);
+            let a = add_at(
+                &path,
+                &Key::new(vec![1], String::new(), String::from("a"), hotp),
+                &e_key,
+            )
+            .unwrap();
+            // Keys with the same name can be stored, as they have different IDs
+            let b = add_at(
+                &path,
+                &Key::new(vec![2], String::new(), String::from("a"), hotp),
+                &e_key,
+            )
+            .unwrap();
+            // A key with the same secret as a stored key is rejected
+            let duplicate = add_at(
+                &path,
+                &Key::new(vec![1], String::new(), String::from("d"), hotp),
+                &e_key,
+            );
+
+            // Rename & set counter, keeping the secret
+            let counted = CodeOptions {
+                method: OTPMethod::HOTP(20),
+                ..hotp
+            };
+            let updated = update_at(
+                &path,
+                a.id,
+                String::from("Example"),
+                String::from("c"),
+                counted,
+                &e_key,
+            )
+            .unwrap();
+            let stored = read_path(&path, &e_key).unwrap();
+            // Options are validated
+            let invalid = CodeOptions { length: 0, ..hotp };
+            let invalid = update_at(
+                &path,
+                a.id,
+                String::new(),
+                String::from("c"),
+                invalid,
+                &e_key,
+            );
+            remove_at(&path, b.id, &e_key).unwrap();
+            let missing = update_at(&path, b.id, String::new(), String::from("b"), hotp, &e_key);
+            let _ = std::fs::remove_dir_all(path.parent().unwrap());
+
+            assert_ne!(a.id, b.id);
+            assert!(matches!(duplicate, Err(Error::Duplicate)));
+            assert_eq!(updated.secret, vec![1]);
+            assert_eq!(stored[0], updated);
+            assert_eq!(stored[0].label(), "Example:c");
+            assert_eq!(stored[0].options.method, OTPMethod::HOTP(20));
+            assert_eq!(stored[1], b);
+            assert!(matches!(invalid, Err(Error::Validation(_))));
+            assert!(matches!(missing, Err(Error::UnknownId(_))));
+        }
+
+        #[test]
+        fn restore_after_password_change() {
+            let path = temp_path("authogonal_restore_after_password_change");
+            let params = encrypt::KdfParams::TEST;
+            let e_key = EncryptionKey::with_params("2082", params).unwrap();
+            let key = Key::new(
+                vec![1],
+                String::new(),
+                String::from("a"),
+                Default::default(),
+            );
+            save_at(&path, &Vec::new(), &e_key).unwrap();
+            add_at(&path, &key, &e_key).unwrap();
+            add_at(
+                &path,
+                &Key::new(
+                    vec![2],
+                    String::new(),
+                    String::from("b"),
+                    Default::default(),
+                ),
+                &e_key,
+            )
+            .unwrap();
+
+            let new_key = EncryptionKey::with_params("4242", params).unwrap();
+            let wrong = change_password_at(&path, "1234", new_key.clone());
+            let new_key = change_password_at(&path, "2082", new_key).unwrap();
+            let staged = backup_paths(&path).iter().any(|b| staged_path(b).exists());
+
+            // The key file is damaged, so its newest backup is unlocked & restored with the new password
+            std::fs::write(&path, "damaged").unwrap();
+            let unlocked = unlock_at(&path, "4242");
+            let restored = restore_latest_at(&path, &new_key);
+            let _ = std::fs::remove_dir_all(path.parent().unwrap());
+
+            assert!(wrong.is_err());
+            assert!(!staged);
+            assert!(unlocked.is_ok());
+            assert_eq!(restored.unwrap().len(), 1);
+        }
+
+        #[test]
+        fn increment_without_backup() {
+            let path = temp_path("authogonal_increment_without_backup");
+            let params = encrypt::KdfParams::TEST;
+            let e_key = EncryptionKey::with_params("2082", params).unwrap();
+            let hotp = CodeOptions::new_or_default(Some(OTPMethod::HOTP(0)), None, None, None);
+            save_at(&path, &Vec::new(), &e_key).unwrap();
+            let key = add_at(
+                &path,
+                &Key::new(vec![1], String::new(), String::from("a"), hotp),
+                &e_key,
+            )
+            .unwrap();
+
+            for _ in 0..=BACKUPS {
+                save_increment_at(&path, &key, &e_key).unwrap();
+            }
+            let stored = read_path(&path, &e_key).unwrap();
+            // The newest backup is still from before the key was added
+            let backup = read_path(&backup_paths(&path)[0], &e_key).unwrap();
+            let _ = std::fs::remove_dir_all(path.parent().unwrap());
+
+            assert_eq!(
+                stored[0].options.method,
+                OTPMethod::HOTP(BACKUPS as u64 + 1)
+            );
+            assert!(backup.is_empty());
+        }
+
+        #[test]
+        fn migrate_legacy() {
+            let path = temp_path("authogonal_migrate_legacy_key");
+            std::fs::copy("src/test_data/keys/legacy", &path).unwrap();
+            // An older backup, from before the file was last saved
+            std::fs::copy("src/test_data/keys/legacy", &backup_paths(&path)[0]).unwrap();
+
+            let e_key = unlock_at(&path, "2082").unwrap();
+            let backups: Vec<_> = backups_at(&path)
+                .iter()
+                .map(|b| read_path(b, &e_key))
+                .collect();
+            let _ = std::fs::remove_dir_all(path.parent().unwrap());
+
+            assert!(!e_key.is_legacy());
+            // The legacy file was backed up before being migrated, and both backups can be read with the new key
+            assert_eq!(backups.len(), 2);
+            assert!(backups.iter().all(Result::is_ok));
+        }
+
+        #[test]
+        fn unlock_missing() {
+            let path = temp_path("authogonal_unlock_missing");
+            let missing = unlock_existing_at(&path, "2082");
+            let created = path.exists();
+            let _ = std::fs::remove_dir_all(path.parent().unwrap());
+
+            assert!(matches!(missing, Err(Error::NoKeyFile)));
+            assert!(!created);
+        }
+
+        #[test]
+        fn ids_assigned() {
+            let key = |id| Key {
+                id,
+                ..Default::default()
+            };
+            let mut keys = vec![key(0), key(4), key(0), key(4), key(2)];
+            assign_ids(&mut keys);
+            let ids: Vec<u64> = keys.iter().map(|k| k.id).collect();
+            assert_eq!(ids, [5, 4, 6, 7, 2]);
+        }
+
+        #[test]
+        fn read_empty_or_invalid() {
+            let path = temp_path("authogonal_read_invalid");
+            let params = encrypt::KdfParams::TEST;
+            let e_key = EncryptionKey::with_params("2082", params).unwrap();
+
+            // Missing and empty files have no keys
+            let missing = read_path(&path, &e_key);
+            encrypt::save(&path, &e_key, String::from("[]")).unwrap();
+            let empty = read_path(&path, &e_key);
+            // Invalid JSON is an error, rather than no keys
+            encrypt::save(&path, &e_key, String::from("[{")).unwrap();
+            let invalid = read_path(&path, &e_key);
+            let _ = std::fs::remove_dir_all(path.parent().unwrap());
+
+            assert!(missing.unwrap().is_empty());
+            assert!(empty.unwrap().is_empty());
+            assert!(matches!(invalid, Err(Error::Parse(_))));
+        }
+    }
+}
+
+/// Handles importing keys from Aegis Authenticator's JSON exports
+/// Encrypted exports wrap a master key in one or more key slots, and the master key encrypts the list of entries
+pub mod aegis {
+    use crate::encoding::{base32, base64, hex};
+    use crate::key::{CodeOptions, Icon, Key};
+    use crate::otp::OTPMethod;
+    use hash::HashFn;
+    use serde::Deserialize;
+    use serde_json::Value;
+
+    /// Type of slot whose key is derived from a password, the others need the phone's biometrics to unlock
+    const PASSWORD_SLOT: u8 = 1;
+
+    /// Top level of an export file
+    #[derive(Deserialize)]
+    struct Export {
+        header: Header,
+        db: Value, // Object of entries if plain, or encrypted Base 64 text
+    }
+
+    /// Details needed to decrypt an export, which are null if it is plain
+    #[derive(Deserialize)]
+    struct Header {
+        slots: Option<Vec<Slot>>,
+        params: Option<CipherParams>,
+    }
+
+    /// A copy of the master key, encrypted with a key derived from a password or biometrics
+    #[derive(Deserialize)]
+    struct Slot {
+        #[serde(rename = "type")]
+        kind: u8,
+        key: String, // Hex
+        key_params: CipherParams,
+        // scrypt parameters, only used by password slots
+        #[serde(default)]
+        n: u64,
+        #[serde(default)]
+        r: u32,
+        #[serde(default)]
+        p: u32,
+        #[serde(default)]
+        salt: String, // Hex
+    }
+
+    /// Nonce & tag of AES-GCM encrypted data, both in hex
+    #[derive(Deserialize)]
+    struct CipherParams {
+        nonce: String,
+        tag: String,
+    }
+
+    /// The list of entries, once decrypted
+    #[derive(Deserialize)]
+    struct Database {
+        entries: Vec<Value>, // Parsed separately, so an unexpected entry doesn't fail the whole import
+        #[serde(default)]
+        groups: Vec<Group>,
+    }
+
+    /// A group that entries can be placed in, which newer exports list separately to the entries
+    #[derive(Deserialize)]
+    struct Group {
+        uuid: String,
+        name: String,
+    }
+
+    /// A single key
+    #[derive(Deserialize)]
+    struct Entry {
+        #[serde(rename = "type")]
+        kind: String,
+        #[serde(default)]
+        name: String,
+        #[serde(default)]
+        issuer: String,
+        group: Option<String>, // Name of the entry's group in older exports
+        #[serde(default)]
+        groups: Vec<String>, // IDs of the entry's groups in newer exports
+        icon: Option<String>,  // Base 64
+        icon_mime: Option<String>,
+        info: Info,
+    }
+
+    /// Code options of an entry
+    #[derive(Deserialize)]
+    struct Info {
+        secret: String, // Base 32
+        algo: Option<String>,
+        digits: Option<u8>,
+        period: Option<u32>,
+        counter: Option<u64>,
+    }
+
+    /// Checks whether an export is encrypted, meaning a password is needed to read it
+    pub fn is_encrypted(text: &str) -> Result<bool, crate::Error> {
+        let export: Export = serde_json::from_str(text).map_err(|_| Error::Format)?;
+        Ok(export.db.is_string())
+    }
+
+    /// Reads the keys from an export, using the password to decrypt it if it's encrypted
+    /// Entries that can't be imported, such as ones of an unsupported type, are returned as errors so the others can still be imported
+    pub fn parse(
+        text: &str,
+        password: &str,
+    ) -> Result<Vec<Result<Key, crate::Error>>, crate::Error> {
+        let export: Export = serde_json::from_str(text).map_err(|_| Error::Format)?;
+        let db: Database = match export.db {
+            Value::String(db) => serde_json::from_slice(&decrypt(&export.header, &db, password)?),
+            db => serde_json::from_value(db),
+        }
+        .map_err(|_| Error::Format)?;
+
+        Ok(db
+            .entries
+            .into_iter()
+            .map(|entry| {
+                let entry = serde_json::from_value(entry).map_err(|_| Error::Format)?;
+                to_key(entry, &db.groups)
+            })
+            .collect())
+    }
+
+    /// Decrypts the list of entries with the master key, which is unlocked by the first password slot the password opens
+    fn decrypt(header: &Header, db: &str, password: &str) -> Result<Vec<u8>, crate::Error> {
+        let (Some(slots), Some(params)) = (&header.slots, &header.params) else {
+            return Err(Error::Format.into());
+        };
+        let mut slots = slots.iter().filter(|s| s.kind == PASSWORD_SLOT).peekable();
+        if slots.peek().is_none() {
+            return Err(Error::NoPasswordSlot.into());
+        }
+
+        let mut master = None;
+        for slot in slots {
+            let salt = hex::decode(&slot.salt).map_err(|_| Error::Format)?;
+            let key = encrypt::external::scrypt(password.as_bytes(), &salt, slot.n, slot.r, slot.p)
+                .map_err(|_| Error::Format)?;
+            let ciphertext = hex::decode(&slot.key).map_err(|_| Error::Format)?;
+            // A slot made with a different password fails to decrypt, so the next is tried
+            if let Ok(key) = open(&key, &slot.key_params, &ciphertext) {
+                master = Some(key);
+                break;
+            }
+        }
+        // No slot could be opened with the password
+        let master = master.ok_or(crate::Error::Decryption)?;
+
+        let ciphertext = base64::decode(db).map_err(|_| Error::Format)?;
+        open(&master, params, &ciphertext)
+    }
+
+    /// Decrypts AES-GCM encrypted data
+    fn open(key: &[u8], params: &CipherParams, ciphertext: &[u8]) -> Result<Vec<u8>, crate::Error> {
+        let nonce = hex::decode(&params.nonce).map_err(|_| Error::Format)?;
+        let tag = hex::decode(&params.tag).map_err(|_| Error::Format)?;
+        encrypt::external::decrypt(key, &nonce, ciphertext, &tag).map_err(|e| match e {
+            encrypt::Error::ReadError => crate::Error::Decryption,
+            _ => Error::Format.into(),
+        })
+    }
+
+    /// Converts an entry into a key, failing if its type or hash function isn't supported
+    fn to_key(entry: Entry, groups: &[Group]) -> Result<Key, crate::Error> {
+        let info = entry.info;
+        let method = match entry.kind.to_ascii_lowercase().as_str() {
+            "totp" => OTPMethod::TOTP,
+            "hotp" => OTPMethod::HOTP(info.counter.unwrap_or(0)),
+            "steam" => OTPMethod::Steam,
+            // mOTP & Yandex codes are generated differently
+            kind => return Err(Error::UnsupportedType(kind.to_string()).into()),
+        };
+        let hash = match info.algo.as_deref().map(str::to_ascii_uppercase).as_deref() {
+            None | Some("SHA1") => HashFn::SHA1,
+            Some("SHA256") => HashFn::SHA256,
+            Some("SHA512") => HashFn::SHA512,
+            Some(algo) => return Err(Error::UnsupportedAlgorithm(algo.to_string()).into()),
+        };
+        let options = match method {
+            // Steam codes always use the same options, whatever the entry lists
+            OTPMethod::Steam => CodeOptions::steam(),
+            // Counter based codes have no period
+            OTPMethod::HOTP(_) => {
+                CodeOptions::new_or_default(Some(method), Some(hash), info.digits, None)
+            }
+            OTPMethod::TOTP => {
+                CodeOptions::new_or_default(Some(method), Some(hash), info.digits, info.period)
+            }
+        };
+
+        let mut key = Key::new(
+            base32::decode(&info.secret)?,
+            entry.issuer,
+            entry.name,
+            options,
+        );
+        // Group IDs are replaced with their names, and an entry in several groups has them all listed
+        let names = entry.groups.iter().filter_map(|id| {
+            groups
+                .iter()
+                .find(|g| &g.uuid == id)
+                .map(|g| g.name.clone())
+        });
+        key.group = entry
+            .group
+            .into_iter()
+            .chain(names)
+            .collect::<Vec<_>>()
+            .join(", ");
+        // An unreadable icon is dropped rather than losing the key, and older exports only stored PNGs so didn't give a type
+        key.icon = entry
+            .icon
+            .and_then(|icon| base64::decode(&icon).ok())
+            .map(|data| Icon {
+                mime: entry.icon_mime.unwrap_or_else(|| "image/png".to_string()),
+                data,
+            });
+        Ok(key)
+    }
+
+    /// Error enum to handle errors with reading Aegis exports
+    #[derive(Debug, PartialEq, Eq)]
+    pub enum Error {
+        Format, // Export isn't in the expected structure, or its encryption details are invalid
+        NoPasswordSlot, // Export can only be unlocked with biometrics
+        UnsupportedType(String), // Entry's codes can't be generated, such as mOTP
+        UnsupportedAlgorithm(String), // Entry uses a hash function that isn't supported, such as MD5
+    }
+
+    impl std::error::Error for Error {}
+
+    impl std::fmt::Display for Error {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                Self::Format => write!(f, "Not a valid Aegis export"),
+                Self::NoPasswordSlot => write!(f, "Export can only be unlocked with biometrics"),
+                Self::UnsupportedType(kind) => write!(f, "Unsupported key type {}", kind),
+                Self::UnsupportedAlgorithm(algo) => write!(f, "Unsupported algorithm {}", algo),
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn plain() {
+            let text = std::fs::read_to_string("src/test_data/aegis/plain.json").unwrap();
+            assert!(!is_encrypted(&text).unwrap());
+            let keys = parse(&text, "").unwrap();
+            assert_eq!(keys.len(), 4);
+
+            let totp = keys[0].as_ref().unwrap();
+            assert_eq!(totp.label(), "Example:alice@example.com");
+            assert_eq!(totp.group, "Work");
+            assert_eq!(
+                totp.options,
+                CodeOptions::new_or_default(
+                    Some(OTPMethod::TOTP),
+                    Some(HashFn::SHA256),
+                    Some(8),
+                    Some(60)
+                )
+            );
+            assert_eq!(
+                keys[1].as_ref().unwrap().options.method,
+                OTPMethod::HOTP(12)
+            );
+            assert_eq!(totp.icon, None);
+            let steam = keys[2].as_ref().unwrap();
+            assert_eq!(steam.options, CodeOptions::steam());
+            let icon = steam.icon.as_ref().unwrap();
+            assert_eq!(icon.mime, "image/png");
+            assert!(icon.data.starts_with(b"\x89PNG"));
+            // mOTP entry is reported without failing the others
+            assert!(matches!(
+                &keys[3],
+                Err(crate::Error::Parse(crate::error::ParseError::Aegis(
+                    Error::UnsupportedType(kind)
+                ))) if kind == "motp"
+            ));
+        }
+
+        #[test]
+        fn encrypted() {
+            let text = std::fs::read_to_string("src/test_data/aegis/encrypted.json").unwrap();
+            assert!(is_encrypted(&text).unwrap());
+            let keys = parse(&text, "test").unwrap();
+            assert_eq!(keys.len(), 4);
+            assert_eq!(
+                keys[0].as_ref().unwrap().label(),
+                "Example:alice@example.com"
+            );
+
+            assert!(matches!(
+                parse(&text, "wrong"),
+                Err(crate::Error::Decryption)
+            ));
+        }
+
+        #[test]
+        fn invalid() {
+            assert!(parse("{}", "").is_err());
+            // Encrypted, but without the details to decrypt it
+            let text = r#"{"header": {"slots": null, "params": null}, "db": "AAAA"}"#;
+            assert!(matches!(
+                parse(text, ""),
+                Err(crate::Error::Parse(crate::error::ParseError::Aegis(
+                    Error::Format
+                )))
+            ));
+        }
     }
 }
 
@@ -105,27 +1144,104 @@ pub mod options {
     use crate::ui::main::AppOptions;
 
     /// Save [AppOptions] to settings file
-    pub fn save(options: &AppOptions) {
+    pub fn save(options: &AppOptions) -> Result<(), Error> {
         // Create path from settings path constant
-        let path = get_dir().join(Path::new(SETTINGSPATH));
+        let path = config_dir()?.join(Path::new(SETTINGSPATH));
         // Create file in path location
-        let file = File::create(path).unwrap();
+        let file = File::create(path)?;
         // Convert AppOptions to pretty JSON and write to file
-        serde_json::to_writer_pretty(file, &options).unwrap();
+        Ok(serde_json::to_writer_pretty(file, &options)?)
     }
 
     /// Load [AppOptions] from settings file
     pub fn load() -> AppOptions {
-        let path = get_dir().join(Path::new(SETTINGSPATH));
-
-        if let Ok(f) = File::open(&path) {
-            if let Ok(v) = serde_json::from_reader(f) {
-                // If file could be read and contains valid JSON, return read data
-                return v;
-            }
+        if let Some(v) = read() {
+            return v;
         }
-        // If data invalid, overwrite it with valid default settings, and return defaults
-        save(&Default::default());
+        // If data invalid, try to overwrite it with valid default settings, and return defaults
+        // Settings aren't essential, so the defaults are still used if they can't be saved
+        let _ = save(&Default::default());
         Default::default()
     }
+
+    /// Reads [AppOptions] from the settings file, without writing defaults if it's missing or invalid
+    /// Used by the command line, which shouldn't create files when only reading
+    pub fn read() -> Option<AppOptions> {
+        let f = File::open(config_dir().ok()?.join(Path::new(SETTINGSPATH))).ok()?;
+        // If file could be read and contains valid JSON, return read data
+        serde_json::from_reader(f).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates an empty directory for a test within the system's temporary directory
+    pub(super) fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn dir_priority() {
+        let exe = temp_dir("authogonal_dir_priority");
+        let platform = Some((PathBuf::from("data"), PathBuf::from("config")));
+        let flag = || Some(PathBuf::from("flag"));
+        let env = || Some(PathBuf::from("env"));
+
+        let chosen = choose_dirs(flag(), env(), &exe, platform.clone());
+        assert_eq!(chosen.data, PathBuf::from("flag"));
+        assert_eq!(chosen.config, PathBuf::from("flag"));
+
+        let chosen = choose_dirs(None, env(), &exe, platform.clone());
+        assert_eq!(chosen.data, PathBuf::from("env"));
+        // Empty variable is ignored
+        let chosen = choose_dirs(None, Some(PathBuf::new()), &exe, platform.clone());
+        assert_eq!(chosen.data, PathBuf::from("data"));
+        assert_eq!(chosen.config, PathBuf::from("config"));
+        assert!(chosen.migrate);
+
+        // No platform directories
+        assert_eq!(choose_dirs(None, None, &exe, None).data, exe);
+
+        // Portable mode
+        std::fs::write(exe.join(PORTABLE_MARKER), "").unwrap();
+        let chosen = choose_dirs(None, None, &exe, platform);
+        let _ = std::fs::remove_dir_all(&exe);
+        assert_eq!(chosen.data, exe);
+        assert!(!chosen.migrate);
+    }
+
+    #[test]
+    fn migrate_legacy() {
+        let legacy = temp_dir("authogonal_migrate_legacy");
+        let dirs = Dirs {
+            data: temp_dir("authogonal_migrate_data"),
+            config: temp_dir("authogonal_migrate_config"),
+            migrate: true,
+        };
+        std::fs::write(legacy.join(KEYPATH), "keys").unwrap();
+        std::fs::write(legacy.join("keys.bak1"), "backup").unwrap();
+        std::fs::write(legacy.join(SETTINGSPATH), "settings").unwrap();
+        // Settings already in the new location aren't replaced
+        std::fs::write(dirs.config.join(SETTINGSPATH), "new").unwrap();
+
+        migrate(&legacy, &dirs).unwrap();
+        let read = |path: PathBuf| std::fs::read_to_string(path).ok();
+        let keys = read(dirs.data.join(KEYPATH));
+        let backup = read(dirs.data.join("keys.bak1"));
+        let settings = read(dirs.config.join(SETTINGSPATH));
+        let moved = !legacy.join(KEYPATH).exists();
+        for dir in [&legacy, &dirs.data, &dirs.config] {
+            let _ = std::fs::remove_dir_all(dir);
+        }
+
+        assert_eq!(keys.as_deref(), Some("keys"));
+        assert_eq!(backup.as_deref(), Some("backup"));
+        assert_eq!(settings.as_deref(), Some("new"));
+        assert!(moved);
+    }
 }
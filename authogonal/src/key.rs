@@ -1,112 +1,145 @@
 // Structs used for the whole library
 
+use crate::encoding::SecretEncoding;
+use crate::error::{Error, ValidationError};
 use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+
+/// Range of code lengths that can be generated
+/// 10 digits is the maximum, as a truncated HMAC is only 31 bits
+pub const LENGTH_RANGE: RangeInclusive<u8> = 4..=10;
 
 /// Stores all data relevant to creating a OTP code
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)] // Doesn't derive Copy, as contains Strings
 pub struct Key {
-    pub secret: String,
-    pub name: String,
+    #[serde(default)]
+    // Unique within the key file, assigned when the key is stored - keys saved before IDs existed are read as 0 until assigned
+    pub id: u64,
+    #[serde(with = "crate::encoding::base32::as_string")]
+    // Raw secret bytes, stored in files as base 32 so older files can still be read
+    pub secret: Vec<u8>,
+    #[serde(default)]
+    // Provider the account belongs to, which may be empty - keys saved before issuers were stored are read with an empty issuer
+    pub issuer: String,
+    #[serde(alias = "name")]
+    // Name of the account with the provider, read from the name field of older files
+    pub account: String,
+    #[serde(default)]
+    // Groups the key was filed under in the app it was imported from, separated by commas, which may be empty
+    pub group: String,
+    #[serde(default)]
+    // Icon shown beside the key by the app it was imported from, kept so it isn't lost though this app doesn't show icons
+    pub icon: Option<Icon>,
     pub options: CodeOptions,
     pub time: i64,
+    #[serde(default)]
+    // Encoding the secret was entered in, used when showing the secret to the user
+    pub encoding: SecretEncoding,
+}
+
+/// Image attached to a key by the app it was imported from
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Icon {
+    // MIME type of the image, such as image/png
+    pub mime: String,
+    #[serde(with = "crate::encoding::base64::as_string")]
+    pub data: Vec<u8>,
 }
 
 impl Key {
     /// Constructor for a [Key]
     /// The time attribute will always be initialised as the current time
-    pub fn new(secret: String, name: String, options: CodeOptions) -> Self {
+    /// The secret is raw bytes, see [Key::decode_secret] to get these from text
+    pub fn new(secret: Vec<u8>, issuer: String, account: String, options: CodeOptions) -> Self {
         let time = chrono::Utc::now().timestamp();
         Self {
+            id: 0, // Assigned when added to the key file
             secret,
-            name,
+            issuer,
+            account,
+            group: String::new(),
+            icon: None,
             options,
             time,
+            encoding: SecretEncoding::default(),
         }
     }
 
     /// Validates that the key can be used to create valid OTP codes
-    /// On error, returns an Err variant containing an error message as a string
-    pub fn validate(&self) -> Result<(), String> {
-        // Validate name not empty
-        if self.name.len() == 0 {
-            return Err(String::from("Name cannot be empty"));
+    pub fn validate(&self) -> Result<(), Error> {
+        // Validate name not empty, where either the issuer or account name is enough to identify the key
+        if self.issuer.is_empty() && self.account.is_empty() {
+            return Err(ValidationError::EmptyName.into());
         }
 
-        let secret = &self.secret;
+        // Validate code length can be generated
+        if !LENGTH_RANGE.contains(&self.options.length) {
+            return Err(ValidationError::CodeLength.into());
+        }
 
-        // Validate secret at least 2 characters
-        if secret.len() <= 1 {
-            return Err(String::from("Invalid secret length"));
+        // Validate timestep can be calculated
+        if self.options.interval == 0 {
+            return Err(ValidationError::Interval.into());
         }
 
-        let secret = secret.to_ascii_uppercase();
-        // ? operator propegates the error through this function
-        Self::validate_char(&secret)?;
-        Self::validate_len(&secret)?;
+        // Validate secret not empty
+        if self.secret.is_empty() {
+            return Err(ValidationError::EmptySecret.into());
+        }
 
         Ok(())
     }
 
-    /// Validate that chars in Base-32 set
-    fn validate_char(secret: &String) -> Result<(), String> {
-        // All characters in base 32 character set
-        let base32chars = "ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+    /// Decodes an entered secret into raw bytes
+    pub fn decode_secret(secret: &str, encoding: SecretEncoding) -> Result<Vec<u8>, Error> {
+        // Decoding is tolerant of formatting, so any error means the secret itself is invalid
+        let bytes = encoding.decode(secret)?;
 
-        // Iterates through all characters, if one isn't in the &str above, the secret must be invalid
-        if secret.chars().any(|c| !base32chars.contains(c)) {
-            return Err(String::from("Invalid character in secret"));
+        // Validate secret contains at least 1 byte
+        if bytes.is_empty() {
+            return Err(ValidationError::EmptySecret.into());
         }
-        Ok(())
-    }
-
-    // Validate any overflow when converting to b32 just contains 0s
-    fn validate_len(secret: &String) -> Result<(), String> {
-        // All characters in base 32 character set
-        let base32chars = "ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
-
-        // Reverse, so calling next will return final item, in order to take only the last 2 chars of the secret
-        let mut backwards = secret.chars().rev();
-        // Unwrap can be used safely, as validation that secret is at least 2 chars has already been performed
-        let last = backwards.next().unwrap();
-        let penultimate = backwards.next().unwrap();
-        // Creates a String representation of the final 10 bits of the secret when converted to base 32
-        // The find method will return the index location of a character, which is the base 32 representation, as base32chars variable stores characters in index order
-        let ending_binary = format!(
-            "{:05b}{:05b}",
-            base32chars.find(penultimate).unwrap(),
-            base32chars.find(last).unwrap()
-        );
 
-        // The right index to start the check from
-        let bit_len = secret.len() * 5;
-        // The number of 'trailing bits' (bits after the final full byte)
-        let rem_i = bit_len - bit_len / 8 * 8;
+        Ok(bytes)
+    }
 
-        let bits: Vec<char> = ending_binary.chars().collect();
-        // Len is always 10, so 10 - rem_i gives left index
-        // If any of the 'trailing bits' are a 1, the secret is invalid
-        if bits[10 - rem_i..].iter().any(|b| *b == '1') {
-            return Err(String::from("Invalid secret"));
+    /// Full name of the key, in the `Issuer:account` form used by key URIs
+    /// Either part is left out along with the colon if it's empty
+    pub fn label(&self) -> String {
+        match (self.issuer.is_empty(), self.account.is_empty()) {
+            (false, false) => format!("{}:{}", self.issuer, self.account),
+            (false, true) => self.issuer.clone(),
+            _ => self.account.clone(),
         }
-        Ok(())
+    }
+
+    /// Encodes the secret in the encoding it was entered in
+    pub fn encoded_secret(&self) -> String {
+        self.encoding.encode(&self.secret)
     }
 
     // This is a method to allow for modification of the struct within Rust's concurrency checker
     /// Increments the contained HOTP counter by 1
-    /// This additionally saves the change to file
-    pub fn increment(&mut self, e_key: &encrypt::EncryptionKey) {
-        crate::file::keys::save_increment(&self, e_key).unwrap();
+    /// This additionally saves the change to file, leaving the counter unchanged if saving fails
+    pub fn increment(&mut self, e_key: &encrypt::EncryptionKey) -> Result<(), Error> {
+        crate::file::keys::save_increment(&self, e_key)?;
         self.options.method.increment_counter();
+        Ok(())
     }
 }
 
 impl std::default::Default for Key {
     fn default() -> Self {
         Self {
-            secret: String::new(),
-            name: String::new(),
+            id: 0,
+            secret: Vec::new(),
+            issuer: String::new(),
+            account: String::new(),
+            group: String::new(),
+            icon: None,
             options: CodeOptions::default(),
             time: chrono::Utc::now().timestamp(),
+            encoding: SecretEncoding::default(),
         }
     }
 }
@@ -162,6 +195,16 @@ impl CodeOptions {
             interval.unwrap_or(30),
         )
     }
+
+    /// Constructor for [CodeOptions] used by Steam Guard, which only supports 5 character SHA1 codes that change every 30 seconds
+    pub fn steam() -> Self {
+        Self::new(
+            crate::otp::OTPMethod::Steam,
+            hash::HashFn::SHA1,
+            crate::otp::STEAM_LENGTH,
+            30,
+        )
+    }
 }
 
 impl std::default::Default for CodeOptions {
@@ -174,49 +217,165 @@ impl std::default::Default for CodeOptions {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::encoding::b32;
 
     #[test]
     fn secret_validate_empty() {
-        Key::new(String::new(), String::new(), Default::default())
+        Key::new(Vec::new(), String::new(), String::new(), Default::default())
             .validate()
             .unwrap_err();
     }
 
     #[test]
     fn secret_validate_non_empty() {
-        Key::new(String::from("7A"), String::from("test"), Default::default())
+        Key::new(
+            b32("7A"),
+            String::new(),
+            String::from("test"),
+            Default::default(),
+        )
+        .validate()
+        .unwrap();
+    }
+
+    #[test]
+    fn length_validate_max() {
+        let options = CodeOptions::new_or_default(None, None, Some(10), None);
+        Key::new(b32("7A"), String::new(), String::from("test"), options)
             .validate()
             .unwrap();
     }
 
+    #[test]
+    fn length_validate_too_long() {
+        let options = CodeOptions::new_or_default(None, None, Some(11), None);
+        Key::new(b32("7A"), String::new(), String::from("test"), options)
+            .validate()
+            .unwrap_err();
+    }
+
+    #[test]
+    fn length_validate_too_short() {
+        let options = CodeOptions::new_or_default(None, None, Some(3), None);
+        Key::new(b32("7A"), String::new(), String::from("test"), options)
+            .validate()
+            .unwrap_err();
+    }
+
+    #[test]
+    fn interval_validate_zero() {
+        let options = CodeOptions::new_or_default(None, None, None, Some(0));
+        let err = Key::new(b32("7A"), String::new(), String::from("test"), options)
+            .validate()
+            .unwrap_err();
+        assert!(matches!(err, Error::Validation(ValidationError::Interval)));
+    }
+
     #[test]
     fn secret_validate_invalid_char() {
-        Key::validate_char(&String::from("2082")).unwrap_err();
+        let err = Key::decode_secret("2082", SecretEncoding::Base32).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Validation(ValidationError::Secret(crate::encoding::Error::InvalidChar))
+        ));
     }
 
     #[test]
     fn secret_validate_valid_char() {
-        Key::validate_char(&String::from("MANONAM")).unwrap();
+        Key::decode_secret("MANONAM", SecretEncoding::Base32).unwrap();
     }
 
     #[test]
     fn secret_validate_invalid_len() {
         // 00000000_1000000
         // ACA
-        Key::validate_len(&String::from("ACA")).unwrap_err();
+        Key::decode_secret("ACA", SecretEncoding::Base32).unwrap_err();
     }
 
     #[test]
     fn secret_validate_valid_len() {
         // 01111100_01010000_11000110_0
         // Primm
-        Key::validate_len(&String::from("PRIMM")).unwrap();
+        Key::decode_secret("PRIMM", SecretEncoding::Base32).unwrap();
     }
 
     #[test]
     fn secret_validate_exact_len() {
         // 10010001_11011101_01101000_10111001_11001100
         // Showroom
-        Key::validate_len(&String::from("SHOWROOM")).unwrap();
+        Key::decode_secret("SHOWROOM", SecretEncoding::Base32).unwrap();
+    }
+
+    #[test]
+    fn secret_validate_formatted() {
+        Key::decode_secret("jbsw-y3dp ehpk-3pxp====", SecretEncoding::Base32).unwrap();
+    }
+
+    #[test]
+    fn secret_decode_encodings() {
+        let expect = b"Hello!\xde\xad\xbe\xef".to_vec();
+        let base32 = Key::decode_secret("JBSWY3DPEHPK3PXP", SecretEncoding::Base32).unwrap();
+        let hex = Key::decode_secret("48656c6c6f21deadbeef", SecretEncoding::Hex).unwrap();
+        let base64 = Key::decode_secret("SGVsbG8h3q2+7w==", SecretEncoding::Base64).unwrap();
+        assert_eq!(base32, expect);
+        assert_eq!(hex, expect);
+        assert_eq!(base64, expect);
+    }
+
+    #[test]
+    fn secret_decode_empty() {
+        Key::decode_secret("  ", SecretEncoding::Hex).unwrap_err();
+    }
+
+    #[test]
+    fn encoded_secret() {
+        let mut key = Key::new(
+            b32("JBSWY3DPEHPK3PXP"),
+            String::new(),
+            String::from("test"),
+            Default::default(),
+        );
+        assert_eq!(key.encoded_secret(), "JBSWY3DPEHPK3PXP");
+        key.encoding = SecretEncoding::Hex;
+        assert_eq!(key.encoded_secret(), "48656c6c6f21deadbeef");
+    }
+
+    #[test]
+    fn label() {
+        let mut key = Key::new(
+            b32("7A"),
+            String::from("Example"),
+            String::from("alice@google.com"),
+            Default::default(),
+        );
+        assert_eq!(key.label(), "Example:alice@google.com");
+        key.account = String::new();
+        assert_eq!(key.label(), "Example");
+        key.validate().unwrap();
+        key.issuer = String::new();
+        assert_eq!(key.label(), "");
+        key.validate().unwrap_err();
+    }
+
+    #[test]
+    fn serde_name() {
+        // Names stored before issuers were separate are read as the account name
+        let json = r#"{"secret":"7A","name":"test","options":{"method":"TOTP","hash":"SHA1","length":6,"interval":30},"time":0}"#;
+        let key: Key = serde_json::from_str(json).unwrap();
+        assert_eq!(key.issuer, "");
+        assert_eq!(key.account, "test");
+    }
+
+    #[test]
+    fn serde_secret() {
+        // Secrets stored as formatted base 32 strings before secrets were stored as bytes can still be read
+        let json = r#"{"secret":"jbsw y3dp ehpk 3pxp","name":"test","options":{"method":"TOTP","hash":"SHA1","length":6,"interval":30},"time":0}"#;
+        let key: Key = serde_json::from_str(json).unwrap();
+        assert_eq!(key.secret, b32("JBSWY3DPEHPK3PXP"));
+        assert_eq!(key.encoding, SecretEncoding::Base32);
+
+        // Secrets are written as canonical base 32, whatever they were entered as
+        let json = serde_json::to_string(&key).unwrap();
+        assert!(json.contains(r#""secret":"JBSWY3DPEHPK3PXP""#));
     }
 }
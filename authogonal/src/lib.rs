@@ -1,10 +1,19 @@
 // Collate external modules into library
-mod file;
+pub mod cli;
+pub mod clock;
+pub mod encoding;
+pub mod error;
+pub mod file;
 mod hmac;
-mod key;
-mod otp;
-mod qr;
+pub mod key;
+pub mod migration;
+pub mod ocra;
+pub mod otp;
+pub mod qr;
 mod thread;
+pub mod uri;
+
+pub use error::Error;
 
 /// GUI related module
 pub mod ui {
@@ -21,6 +30,43 @@ pub mod ui {
     // Application version from manifest
     const VERSION: &str = env!("CARGO_PKG_VERSION");
 
+    /// Fields used to change the key file's password, shared by the password window & the options tab
+    #[derive(Default)]
+    struct PasswordFields {
+        current: String,
+        new: String,
+        confirm: String,
+    }
+
+    impl PasswordFields {
+        /// Draws a hidden text box for each field
+        fn draw(&mut self, ui: &mut egui::Ui) {
+            for (field, hint) in [
+                (&mut self.current, "Current password"),
+                (&mut self.new, "New password"),
+                (&mut self.confirm, "Confirm new password"),
+            ] {
+                ui.add(
+                    egui::TextEdit::singleline(field)
+                        .password(true)
+                        .hint_text(hint),
+                );
+            }
+        }
+
+        /// Re-encrypts the key file with the new password, returning the new encryption key, or a message to show the user
+        /// All fields are cleared on success, so the passwords don't stay in memory
+        fn change(&mut self) -> Result<EncryptionKey, String> {
+            if self.new != self.confirm {
+                return Err(String::from("New passwords don't match"));
+            }
+            let e_key =
+                file::keys::change_password(&self.current, &self.new).map_err(|e| e.to_string())?;
+            *self = Self::default();
+            Ok(e_key)
+        }
+    }
+
     /// Handles the main window
     pub mod main {
         use super::*;
@@ -29,9 +75,13 @@ pub mod ui {
         use hash_table::hash_map::HashMap;
         use serde::{Deserialize, Serialize};
         use std::sync::mpsc::{Receiver, Sender};
+        use std::sync::Arc;
 
-        use crate::key::Key;
-        use crate::otp::{OTPMethod, OTPMethodStripped};
+        use crate::clock::{self, Clock, OffsetClock, SystemClock};
+        use crate::encoding::SecretEncoding;
+        use crate::key::{CodeOptions, Key, LENGTH_RANGE};
+        use crate::ocra;
+        use crate::otp::{self, OTPMethod, OTPMethodStripped};
         use crate::qr;
         use crate::thread;
         use sort::merge_sort;
@@ -39,8 +89,9 @@ pub mod ui {
         // Message from thread -> app
         #[derive(Debug)]
         pub enum OTPMessageOut {
-            Code(u32), // Code to display
-            Tick(f32), // To update progress bar
+            Code(String),  // Rendered code to display
+            Tick(f32),     // To update progress bar
+            Error(String), // Message shown when the counter couldn't be saved
         }
 
         // Message from app -> thread
@@ -54,9 +105,11 @@ pub mod ui {
         /// Held by the app instance to be used to display a code
         #[derive(Clone)]
         struct DisplayKey {
-            code: u32,
-            length: u8,
-            name: String,
+            code: String,
+            id: u64, // ID of the key in the key file, used to identify it as names may be shared
+            issuer: String,
+            account: String,
+            group: String, // Groups the key was in when imported, only used for searching
             sender: Sender<OTPMessageIn>, // Additionally stores a sender to act as a link between application and an individual key's thread
             time: i64,
             method: OTPMethodStripped,
@@ -65,16 +118,20 @@ pub mod ui {
 
         impl DisplayKey {
             fn new(
-                name: String,
-                length: u8,
+                id: u64,
+                issuer: String,
+                account: String,
+                group: String,
                 sender: Sender<OTPMessageIn>,
                 time: i64,
                 method: OTPMethodStripped,
             ) -> Self {
                 Self {
-                    code: 0, // Code updated on thread startup
-                    length,
-                    name,
+                    code: String::new(), // Code updated on thread startup
+                    id,
+                    issuer,
+                    account,
+                    group,
                     sender,
                     time,
                     method,
@@ -82,15 +139,29 @@ pub mod ui {
                 }
             }
 
-            // Converts code to String to be displayed
+            /// Whether the issuer, account name or group contains the search text, ignoring case
+            fn matches(&self, search: &str) -> bool {
+                let search = search.to_lowercase();
+                self.issuer.to_lowercase().contains(&search)
+                    || self.account.to_lowercase().contains(&search)
+                    || self.group.to_lowercase().contains(&search)
+            }
+
+            // Spaces out code to be displayed
             fn generate_code_string(&self, spacer: bool) -> String {
-                // Converts self.length into usize to be used as a length
-                let d: usize = self.length.into();
-                // Creates a string of a d length representation of the self.code, padded with leading 0s if necessary
-                let mut code = format!("{:0>d$}", self.code, d = d);
-                // Insert space in centre if requested and the length is of an even number
-                if spacer && code.len() % 2 == 0 {
-                    code.insert(code.len() / 2, ' ')
+                let mut code = self.code.clone();
+                let d = code.len();
+                if spacer {
+                    match d {
+                        // 9 digit codes are split into 3 groups of 3
+                        9 => {
+                            code.insert(6, ' ');
+                            code.insert(3, ' ');
+                        }
+                        // Insert space in centre if the length is of an even number, or if the code is long enough to be hard to read otherwise
+                        4 | 6 | 7 | 8 | 10 => code.insert(d / 2, ' '),
+                        _ => (),
+                    }
                 }
                 code
             }
@@ -100,7 +171,10 @@ pub mod ui {
         #[derive(PartialEq, Serialize, Deserialize)]
         enum SortBy {
             Date, // Oldest one added will have lowest id, so displayed first
-            Name, // Displayed alphabetically ascending
+            #[serde(alias = "Name")]
+            // Sorting by name before issuers were separate is closest to sorting by issuer
+            Issuer, // Displayed alphabetically ascending by issuer, then account name
+            Account, // Displayed alphabetically ascending by account name, then issuer
         }
 
         impl Default for SortBy {
@@ -114,6 +188,7 @@ pub mod ui {
         enum Tab {
             Main,
             Add,
+            Ocra,
             Options,
             About,
         }
@@ -124,18 +199,168 @@ pub mod ui {
                 match self {
                     Self::Main => String::from("Main"),
                     Self::Add => String::from("Add"),
+                    Self::Ocra => String::from("OCRA"),
                     Self::Options => String::from("Options"),
                     Self::About => String::from("About"),
                 }
             }
         }
 
+        /// Stores the state of all fields in the OCRA tab
+        /// OCRA secrets aren't saved to file, so are only held here while the app is open
+        struct OcraFields {
+            secret: String,
+            encoding: SecretEncoding,
+            suite: String,
+            challenge: String,
+            counter: u64,
+            pin: String,
+            session: String,
+            response: String,
+            err: String,
+        }
+
+        impl Default for OcraFields {
+            fn default() -> Self {
+                Self {
+                    secret: String::new(),
+                    encoding: SecretEncoding::default(),
+                    suite: String::from("OCRA-1:HOTP-SHA1-6:QN08"), // Most basic suite, a one-way challenge-response
+                    challenge: String::new(),
+                    counter: 0,
+                    pin: String::new(),
+                    session: String::new(),
+                    response: String::new(),
+                    err: String::new(),
+                }
+            }
+        }
+
+        impl OcraFields {
+            /// Calculates the response from the entered fields, setting the error message if any are invalid
+            fn respond(&mut self, clock: &dyn Clock) {
+                self.response = String::new();
+
+                let Ok(suite) = self.suite.parse::<ocra::Suite>() else {
+                    self.err = String::from("Invalid suite");
+                    return;
+                };
+
+                // Secret decoded in the same way as a key's
+                let secret = match Key::decode_secret(&self.secret, self.encoding) {
+                    Ok(secret) => secret,
+                    Err(e) => {
+                        self.err = e.to_string();
+                        return;
+                    }
+                };
+
+                let input = ocra::DataInput {
+                    counter: Some(self.counter),
+                    challenge: self.challenge.clone(),
+                    pin: Some(self.pin.clone()),
+                    session: Some(self.session.clone()),
+                    timestamp: Some(clock.now().timestamp()),
+                };
+
+                match ocra::generate(&suite, &secret, &input) {
+                    Ok(r) => {
+                        self.response = r;
+                        self.err = String::new();
+                    }
+                    Err(ocra::Error::InvalidChallenge) => {
+                        self.err = String::from("Challenge doesn't match suite")
+                    }
+                    Err(ocra::Error::InvalidSession) => {
+                        self.err = String::from("Invalid session information")
+                    }
+                    Err(_) => self.err = String::from("An error occurred"),
+                }
+            }
+        }
+
+        /// Fields used to edit a stored key, held while the edit screen is open
+        struct EditFields {
+            id: u64, // ID of the key being edited
+            issuer: String,
+            account: String,
+            options: CodeOptions,
+            secret: Vec<u8>, // Kept to show the code at a chosen time, but can't be changed
+            at: String,      // Time entered to show the code at
+            err: String,
+        }
+
+        /// An encrypted Aegis export, held while its password is being entered
+        struct AegisFields {
+            text: String, // Contents of the export file
+            password: String,
+        }
+
+        impl EditFields {
+            fn new(key: &Key) -> Self {
+                Self {
+                    id: key.id,
+                    issuer: key.issuer.clone(),
+                    account: key.account.clone(),
+                    options: key.options,
+                    secret: key.secret.clone(),
+                    at: String::new(),
+                    err: String::new(),
+                }
+            }
+
+            /// The code generated at the entered time with the entered options, or None if either is invalid
+            /// The time is used in place of the clock, so the time offset setting doesn't apply to it
+            fn code_at(&self) -> Option<String> {
+                let timestamp = clock::parse_time(self.at.trim())?;
+                let key = Key::new(
+                    self.secret.clone(),
+                    self.issuer.clone(),
+                    self.account.clone(),
+                    self.options,
+                );
+                key.validate().ok()?;
+                Some(otp::generate_at(&key, timestamp))
+            }
+        }
+
+        /// A key read from a QR code or an export, listed on the import screen so the user can choose whether to add it
+        struct ImportItem {
+            key: Result<Key, String>, // Message describing why the code couldn't be read as a key, if it couldn't
+            selected: bool,
+            added: bool,
+            status: RichText, // Result of importing, either confirming the key was added or why it wasn't
+        }
+
+        impl ImportItem {
+            fn new(key: Result<Key, crate::Error>) -> Self {
+                Self {
+                    selected: key.is_ok(), // Every readable key is selected to begin with
+                    key: key.map_err(|e| e.to_string()),
+                    added: false,
+                    status: RichText::default(),
+                }
+            }
+        }
+
         /// This struct is held in app memory and stores all the user's preferences about the program
         #[derive(Serialize, Deserialize)]
+        #[serde(default)] // Options missing from the settings file are set to default, so older files can still be read
         pub struct AppOptions {
             sort: SortBy,
             spacer: bool,
             accent: [u8; 3],
+            time_offset: i64, // Seconds to shift the time used for time based codes by
+        }
+
+        impl AppOptions {
+            /// Creates the [Clock] used to generate codes, offset from the system time by the user's chosen offset
+            pub(crate) fn clock(&self) -> Arc<dyn Clock> {
+                Arc::new(OffsetClock::new(
+                    SystemClock,
+                    chrono::Duration::seconds(self.time_offset),
+                ))
+            }
         }
 
         impl Default for AppOptions {
@@ -144,6 +369,7 @@ pub mod ui {
                     sort: Default::default(),
                     spacer: true,
                     accent: [118, 187, 232],
+                    time_offset: 0,
                 }
             }
         }
@@ -153,15 +379,16 @@ pub mod ui {
             ctx: &egui::Context,
             keys: Vec<Key>,
             sort: &SortBy,
-        ) -> (Vec<DisplayKey>, HashMap<String, Receiver<OTPMessageOut>>) {
+            clock: &Arc<dyn Clock>,
+        ) -> (Vec<DisplayKey>, HashMap<u64, Receiver<OTPMessageOut>>) {
             let mut display_keys = Vec::new();
             // Hashmap size static during runtime, as many new keys are unlikely to be added at once
             let mut receivers = HashMap::new_with_size(keys.len() + 8);
 
             // Iterate through all keys, generating a DisplayKey and Receiver and adding it to its respective data structure
             for key in keys {
-                let (key, receiver) = generate_display_key(ctx, &key);
-                receivers.insert(key.name.clone(), receiver);
+                let (key, receiver) = generate_display_key(ctx, &key, clock);
+                receivers.insert(key.id, receiver);
                 display_keys.push(key)
             }
             // Original Keys go out of scope here, being dropped from memory
@@ -175,13 +402,16 @@ pub mod ui {
         fn generate_display_key(
             ctx: &egui::Context,
             key: &Key,
+            clock: &Arc<dyn Clock>,
         ) -> (DisplayKey, Receiver<OTPMessageOut>) {
             // Spawns a thread from the key and saves the Receiver and Sender for 2 way messaging
-            let (receive, send) = thread::spawn_thread(&ctx, &key);
+            let (receive, send) = thread::spawn_thread(&ctx, &key, clock.clone());
             // Creates new display key from attributes of the key
             let display_key = DisplayKey::new(
-                (key.name).to_string(),
-                key.options.length,
+                key.id,
+                key.issuer.clone(),
+                key.account.clone(),
+                key.group.clone(),
                 send,
                 key.time,
                 key.options.method.strip(),
@@ -190,12 +420,98 @@ pub mod ui {
             (display_key, receive)
         }
 
+        /// Draws the entries for each of a key's [CodeOptions], used both when adding & editing a key
+        fn draw_code_options(ui: &mut egui::Ui, options: &mut CodeOptions) {
+            ui.horizontal(|ui| {
+                // Key type entry with radio buttons
+                // Compared without the counter, so a HOTP key is selected whatever its count
+                ui.label("Method");
+                let method = options.method.strip();
+                if ui
+                    .radio(method == OTPMethodStripped::TOTP, "TOTP")
+                    .clicked()
+                {
+                    options.method = OTPMethod::TOTP;
+                }
+                if ui
+                    .radio(method == OTPMethodStripped::HOTP, "HOTP")
+                    .clicked()
+                    && method != OTPMethodStripped::HOTP
+                {
+                    options.method = OTPMethod::HOTP(0); // Counter defaults to 0
+                }
+                if ui
+                    .radio(method == OTPMethodStripped::Steam, "Steam")
+                    .clicked()
+                {
+                    options.method = OTPMethod::Steam;
+                }
+            });
+
+            // Counter entry, only for counter based keys
+            if let OTPMethod::HOTP(counter) = &mut options.method {
+                ui.horizontal(|ui| {
+                    ui.label("Counter ");
+                    ui.add(egui::DragValue::new(counter).speed(0.1));
+                });
+            }
+
+            // Steam Guard codes have fixed options, so the other options can't be changed
+            let steam = options.method == OTPMethod::Steam;
+            if steam {
+                *options = CodeOptions::steam();
+            }
+            ui.add_enabled_ui(!steam, |ui| {
+                ui.horizontal(|ui| {
+                    // Code length entry with integer selection
+                    ui.label("Length  ");
+                    ui.add(
+                        egui::DragValue::new(&mut options.length)
+                            .speed(0.05) // Very low speed, as range is small
+                            .clamp_range(LENGTH_RANGE),
+                    );
+                });
+                ui.horizontal(|ui| {
+                    // Hash function entry with radio buttons
+                    ui.label("Hash Fn ");
+                    ui.radio_value(&mut options.hash, hash::HashFn::SHA1, "SHA1");
+                    ui.radio_value(&mut options.hash, hash::HashFn::SHA256, "SHA256");
+                    ui.radio_value(&mut options.hash, hash::HashFn::SHA512, "SHA512");
+                });
+                ui.horizontal(|ui| {
+                    // Time interval entry with integer selection
+                    ui.label("Interval");
+                    ui.add(
+                        egui::DragValue::new(&mut options.interval)
+                            .speed(0.2) // Low speed for fine slider control
+                            .clamp_range(10..=300), // Clamped between 10 and 300 incl.
+                    );
+                });
+            });
+        }
+
+        /// Draws a radio button for each secret encoding
+        fn draw_encoding(ui: &mut egui::Ui, encoding: &mut SecretEncoding) {
+            for choice in [
+                SecretEncoding::Base32,
+                SecretEncoding::Hex,
+                SecretEncoding::Base64,
+            ] {
+                ui.radio_value(encoding, choice, choice.to_string());
+            }
+        }
+
         /// Sorts using merge sort based on user choice
         fn sort_keys(keys: Vec<DisplayKey>, sort: &SortBy) -> Vec<DisplayKey> {
             // Passes in a different closure (first citizen function) to change how the list is sorted, using the merge_sort crate
             match sort {
                 SortBy::Date => merge_sort(&keys, |v| v.time),
-                SortBy::Name => merge_sort(&keys, |v| v.name.to_uppercase()),
+                SortBy::Issuer => merge_sort(&keys, |v| {
+                    (v.issuer.to_uppercase(), v.account.to_uppercase())
+                }),
+                SortBy::Account => merge_sort(&keys, |v| {
+                    (v.account.to_uppercase(), v.issuer.to_uppercase())
+                }),
             }
         }
 
@@ -215,7 +531,8 @@ pub mod ui {
                     .with_inner_size(egui::vec2(320., 344.))
                     .with_resizable(false)
                     .with_icon(icon)
-                    .with_maximize_button(false),
+                    .with_maximize_button(false)
+                    .with_drag_and_drop(true), // QR code images can be dropped onto the add tab
                 centered: true,
                 ..Default::default()
             };
@@ -230,11 +547,25 @@ pub mod ui {
         struct App {
             encryption_key: EncryptionKey,
             keys: Vec<DisplayKey>,
-            receivers: HashMap<String, Receiver<OTPMessageOut>>, // Thread receivers separate to keys as cannot be cloned - 1-1 relationship between name and thread, as name unique
+            receivers: HashMap<u64, Receiver<OTPMessageOut>>, // Thread receivers separate to keys as cannot be cloned - 1-1 relationship between ID and thread, as ID unique
             tab: Tab,
             add_key: Key,
+            add_secret: String, // Secret text as entered, only decoded into add_key when adding
+            add_uri: String,    // Key URI as pasted into the add tab
             options: AppOptions,
+            clock: Arc<dyn Clock>, // Shared with every key's thread
             add_err: String,
+            options_err: String,
+            main_err: String, // Set when a key on the main tab couldn't be changed, such as when removing it fails
+            load_err: String, // Set when the key file couldn't be read, which shows the recovery screen
+            recovery_msg: RichText, // Result of the last recovery action
+            password: PasswordFields,
+            password_msg: RichText, // Result of changing the password, shown in the options tab
+            ocra: OcraFields,
+            editing: Option<EditFields>, // Set when a key is being edited, which replaces the main tab with the edit screen
+            importing: Option<Vec<ImportItem>>, // Set when an image had several QR codes or an export was read, which replaces the add tab with the import screen
+            aegis: Option<AegisFields>, // Set when an encrypted export is chosen, which replaces the add tab with a password prompt
+            search: String,             // Text entered in the main tab's search box
             to_delete: Option<DisplayKey>,
         }
 
@@ -243,28 +574,42 @@ pub mod ui {
             fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
                 // Updates any codes that have been messaged in by other threads
                 self.update_codes();
+                // If the key file couldn't be read, only the recovery screen is shown
+                if !self.load_err.is_empty() {
+                    self.draw_recovery(ctx);
+                    return;
+                }
                 // Draws tab bar (as always present)
                 self.draw_menu(&ctx);
                 // Draws correct window body, depending on which tab is currently selected
                 match self.tab {
                     Tab::Main => self.draw_main(&ctx),
                     Tab::Add => self.draw_add(&ctx),
+                    Tab::Ocra => self.draw_ocra(&ctx),
                     Tab::Options => self.draw_options(&ctx),
                     Tab::About => self.draw_about(&ctx),
                 }
 
                 // As keys can't be deleted when being iterated through, they are saved in to_delete attribute and done here
-                if let Some(k) = &self.to_delete {
-                    file::keys::remove(&k.name, &self.encryption_key);
+                // Taken so the key isn't attempted to be deleted twice
+                if let Some(k) = self.to_delete.take() {
+                    // Key may have already been removed from file, in which case it only needs removing from the display
+                    // Otherwise it's kept, so the display doesn't lose a key that's still saved
+                    match file::keys::remove(k.id, &self.encryption_key) {
+                        Ok(()) | Err(crate::Error::UnknownId(_)) => self.main_err = String::new(),
+                        Err(e) => {
+                            self.main_err = e.to_string();
+                            return;
+                        }
+                    }
 
                     // Request the respective thread to close, otherwise it would continue running, unnecessarily using system resources
-                    k.sender.send(OTPMessageIn::Close).unwrap();
+                    // Thread may have already closed, in which case there's nothing to do
+                    let _ = k.sender.send(OTPMessageIn::Close);
                     // Remove from internal state
                     self.keys
-                        .remove(self.keys.iter().position(|x| x.name == k.name).unwrap());
-                    self.receivers.remove(&k.name);
-                    // Resets attribute so key isn't attempted to be deleted twice
-                    self.to_delete = None;
+                        .remove(self.keys.iter().position(|x| x.id == k.id).unwrap());
+                    self.receivers.remove(&k.id);
                 }
             }
         }
@@ -276,19 +621,38 @@ pub mod ui {
                 let options = file::options::load();
 
                 // Loads keys and converts them into display keys
-                let keys = file::keys::load(&encryption_key);
+                let clock = options.clock();
+                // An unreadable key file is shown as an error, rather than as having no keys
+                let (keys, load_err) = match file::keys::load(&encryption_key) {
+                    Ok(keys) => (keys, String::new()),
+                    Err(e) => (Vec::new(), e.to_string()),
+                };
                 let (display_keys, receivers) =
-                    generate_display_keys(&cc.egui_ctx, keys, &options.sort);
+                    generate_display_keys(&cc.egui_ctx, keys, &options.sort, &clock);
 
                 // Returns App type with loaded keys and options, other attributes are set to default
                 let app = Self {
                     encryption_key,
                     keys: display_keys,
                     receivers,
-                    options: file::options::load(),
+                    options,
+                    clock,
                     tab: Tab::Main,
                     add_key: Key::default(),
+                    add_secret: String::new(),
+                    add_uri: String::new(),
                     add_err: String::new(),
+                    options_err: String::new(),
+                    main_err: String::new(),
+                    load_err,
+                    recovery_msg: Default::default(),
+                    password: Default::default(),
+                    password_msg: Default::default(),
+                    ocra: Default::default(),
+                    editing: None,
+                    importing: None,
+                    aegis: None,
+                    search: String::new(),
                     to_delete: None,
                 };
 
@@ -305,7 +669,7 @@ pub mod ui {
             fn update_codes(&mut self) {
                 // Iterate through all keys, checking to see if any have data to receive
                 for key in &mut self.keys {
-                    while let Ok(v) = self.receivers.get(&key.name).unwrap().try_recv() {
+                    while let Ok(v) = self.receivers.get(&key.id).unwrap().try_recv() {
                         match v {
                             OTPMessageOut::Code(c) => {
                                 // If a code is received, update the key's old code with the new one
@@ -314,11 +678,43 @@ pub mod ui {
                             OTPMessageOut::Tick(p) => {
                                 key.progress = p;
                             }
+                            OTPMessageOut::Error(e) => self.main_err = e,
                         }
                     }
                 }
             }
 
+            /// Closes all key threads and spawns new ones from the key file
+            /// Used when a change affects every thread, such as the clock being replaced
+            fn reload_keys(&mut self, ctx: &egui::Context) {
+                for key in &self.keys {
+                    // Thread may have already closed, in which case there's nothing to do
+                    let _ = key.sender.send(OTPMessageIn::Close);
+                }
+
+                // If the file can't be read, the recovery screen is shown instead
+                let keys = match file::keys::load(&self.encryption_key) {
+                    Ok(keys) => {
+                        self.load_err = String::new();
+                        keys
+                    }
+                    Err(e) => {
+                        self.load_err = e.to_string();
+                        Vec::new()
+                    }
+                };
+                (self.keys, self.receivers) =
+                    generate_display_keys(ctx, keys, &self.options.sort, &self.clock);
+            }
+
+            /// Saves the options to the settings file, showing an error in the options tab if they couldn't be saved
+            fn save_options(&mut self) {
+                self.options_err = match file::options::save(&self.options) {
+                    Ok(()) => String::new(),
+                    Err(e) => e.to_string(),
+                };
+            }
+
             fn update_accent(&self, ctx: &egui::Context) {
                 ctx.style_mut(|style| {
                     let accent_srgb = Color32::from_rgb(
@@ -363,23 +759,57 @@ pub mod ui {
                 // Create top panel with id Menu and call the macro defined above to draw tabs to the tab bar
                 egui::TopBottomPanel::top("Menu").show(ctx, |ui| {
                     ui.horizontal(|ui| {
-                        menu_tabs!(ui, Tab::Main, Tab::Add, Tab::Options, Tab::About);
+                        menu_tabs!(ui, Tab::Main, Tab::Add, Tab::Ocra, Tab::Options, Tab::About);
                     })
                 });
             }
 
             /// Draw the main tab to the window
             fn draw_main(&mut self, ctx: &egui::Context) {
+                if self.editing.is_some() {
+                    self.draw_edit(ctx);
+                    return;
+                }
+
                 egui::CentralPanel::default().show(ctx, |ui| {
+                    // Every code is shifted by the offset, so it's shown whenever one is set
+                    if self.options.time_offset != 0 {
+                        ui.label(
+                            RichText::new(format!(
+                                "Codes are offset by {:+}s, see Options",
+                                self.options.time_offset
+                            ))
+                            .color(Color32::YELLOW),
+                        );
+                    }
+                    if !self.main_err.is_empty() {
+                        ui.label(RichText::new(&self.main_err).color(Color32::RED));
+                    }
+                    ui.horizontal(|ui| {
+                        // Search entry, filtering keys by issuer, account name & group as it's typed
+                        ui.label("Search");
+                        ui.text_edit_singleline(&mut self.search);
+                    });
+                    ui.separator();
+
                     // Allow for scrolling
                     egui::ScrollArea::vertical().show(ui, |ui| {
-                        // Iterate through keys
-                        for key in &self.keys {
-                            // Uses key name as internal ID to keep track of which element has been clicked
-                            ui.push_id(&key.name, |ui| {
+                        // Iterate through keys matching the search, which is every key if it's empty
+                        for key in self.keys.iter().filter(|k| k.matches(&self.search)) {
+                            // Uses key ID as internal ID to keep track of which element has been clicked
+                            ui.push_id(key.id, |ui| {
                                 let response = ui // Draw individual key to screen & bind to any interactions (clicks) that occur
                                     .vertical(|ui| {
-                                        ui.label(egui::RichText::new(&*key.name).size(20.)); // Display name
+                                        // Display issuer, with the account name below it in smaller text
+                                        // Keys without an issuer only show the account name, in its place
+                                        if key.issuer.is_empty() {
+                                            ui.label(RichText::new(&*key.account).size(20.));
+                                        } else {
+                                            ui.label(RichText::new(&*key.issuer).size(20.));
+                                            if !key.account.is_empty() {
+                                                ui.label(RichText::new(&*key.account).weak());
+                                            }
+                                        }
                                         ui.label(
                                             // Display code in bold and large text size, using the generate_code_string to get the code as a string
                                             egui::RichText::new(
@@ -388,8 +818,8 @@ pub mod ui {
                                             .size(30.)
                                             .strong(),
                                         );
-                                        // If the key is a TOTP, show a progress bar that updates every second
-                                        if let OTPMethodStripped::TOTP = key.method {
+                                        // If the key is time based, show a progress bar that updates every second
+                                        if key.method.is_timed() {
                                             ui.add(
                                                 egui::widgets::ProgressBar::new(key.progress)
                                                     .desired_height(3.),
@@ -402,9 +832,9 @@ pub mod ui {
                                 // Send message to key's thread to increment the counter when clicked
                                 // DisplayKeys don't store the type that the key is meaning it cannot be checked for, however the message will be ignored if the key is TOTP, so its fine to send message to either type
                                 if response.interact(egui::Sense::click()).clicked() {
-                                    key.sender
-                                        .send(OTPMessageIn::Increment(self.encryption_key.clone()))
-                                        .unwrap();
+                                    let _ = key
+                                        .sender
+                                        .send(OTPMessageIn::Increment(self.encryption_key.clone()));
                                 }
 
                                 // If right clicked, create a context menu (popup) with option to delete the key
@@ -424,7 +854,21 @@ pub mod ui {
 
                                         // Copy button
                                         if ui.button("Copy").clicked() {
-                                            ui.output_mut(|o| o.copied_text = key.code.to_string());
+                                            ui.output_mut(|o| o.copied_text = key.code.clone());
+                                            ui.memory_mut(|mem| mem.close_popup())
+                                        }
+
+                                        // Edit button, which opens the edit screen with the key's stored options
+                                        if ui.button("Edit").clicked() {
+                                            if let Some(stored) =
+                                                file::keys::load(&self.encryption_key)
+                                                    .ok()
+                                                    .and_then(|keys| {
+                                                        keys.into_iter().find(|k| k.id == key.id)
+                                                    })
+                                            {
+                                                self.editing = Some(EditFields::new(&stored));
+                                            }
                                             ui.memory_mut(|mem| mem.close_popup())
                                         }
 
@@ -443,61 +887,218 @@ pub mod ui {
                 });
             }
 
+            /// Draw the edit screen to the window, in place of the main tab
+            /// The secret can't be changed, so only the issuer, account name & options are shown
+            fn draw_edit(&mut self, ctx: &egui::Context) {
+                let Some(fields) = &mut self.editing else {
+                    return;
+                };
+                let (mut save, mut cancel) = (false, false);
+
+                egui::CentralPanel::default().show(ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Issuer ");
+                        ui.text_edit_singleline(&mut fields.issuer);
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Account");
+                        ui.text_edit_singleline(&mut fields.account);
+                    });
+                    draw_code_options(ui, &mut fields.options);
+                    // Shows the code at another time, such as to check a code that was rejected, without changing the clock every key uses
+                    if fields.options.method.strip().is_timed() {
+                        ui.horizontal(|ui| {
+                            ui.label("Code at");
+                            ui.add(
+                                egui::TextEdit::singleline(&mut fields.at)
+                                    .hint_text("2024-01-01T12:00:00Z")
+                                    .desired_width(150.),
+                            )
+                            .on_hover_text("UNIX timestamp or RFC 3339 date");
+                            if let Some(code) = fields.code_at() {
+                                ui.label(RichText::new(code).monospace());
+                            }
+                        });
+                    }
+                    ui.vertical_centered(|ui| {
+                        ui.label(RichText::new(&fields.err).color(Color32::RED))
+                    });
+
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        save = ui.button("Save").clicked();
+                        cancel = ui.button("Cancel").clicked();
+                    });
+                });
+
+                // Fields are borrowed while drawing, so are only used afterwards
+                if save {
+                    self.apply_edit(ctx);
+                } else if cancel {
+                    self.editing = None;
+                }
+            }
+
+            /// Saves the edited key to file, then replaces its thread so codes are generated with the new options
+            fn apply_edit(&mut self, ctx: &egui::Context) {
+                let Some(fields) = &mut self.editing else {
+                    return;
+                };
+                let updated = file::keys::update(
+                    fields.id,
+                    fields.issuer.clone(),
+                    fields.account.clone(),
+                    fields.options,
+                    &self.encryption_key,
+                );
+
+                match updated {
+                    Err(e) => fields.err = e.to_string(),
+                    Ok(stored) => {
+                        // Close the old thread, which still has the old name & options
+                        if let Some(i) = self.keys.iter().position(|k| k.id == fields.id) {
+                            let _ = self.keys.remove(i).sender.send(OTPMessageIn::Close);
+                        }
+                        self.receivers.remove(&fields.id);
+
+                        // ID is unchanged, so the new receiver replaces the old one
+                        let (key, receiver) = generate_display_key(ctx, &stored, &self.clock);
+                        self.receivers.insert(key.id, receiver);
+                        self.keys.push(key);
+                        self.keys = sort_keys(self.keys.clone(), &self.options.sort);
+
+                        self.editing = None;
+                    }
+                }
+            }
+
+            /// Draw the recovery screen to the window, in place of the tabs
+            /// Shown when the key file couldn't be read, so that it isn't overwritten by adding to what looks like an empty list
+            fn draw_recovery(&mut self, ctx: &egui::Context) {
+                egui::CentralPanel::default().show(ctx, |ui| {
+                    ui.vertical_centered(|ui| {
+                        ui.style_mut().spacing.item_spacing[1] += 4.;
+
+                        ui.label(RichText::new("Stored keys couldn't be read").size(20.));
+                        ui.label(RichText::new(&self.load_err).color(Color32::RED));
+
+                        ui.separator();
+
+                        // Try reading the file again, such as after it has been fixed by hand
+                        if ui.button("Retry").clicked() {
+                            self.reload_keys(ctx);
+                            self.recovery_msg = RichText::default();
+                        }
+
+                        if ui.button("Restore Backup").clicked() {
+                            match file::keys::restore_latest(&self.encryption_key) {
+                                Ok(keys) => {
+                                    (self.keys, self.receivers) = generate_display_keys(
+                                        ctx,
+                                        keys,
+                                        &self.options.sort,
+                                        &self.clock,
+                                    );
+                                    self.load_err = String::new();
+                                    self.recovery_msg = RichText::default();
+                                }
+                                Err(e) => {
+                                    self.recovery_msg =
+                                        RichText::new(e.to_string()).color(Color32::RED)
+                                }
+                            }
+                        }
+
+                        // Save the decrypted file as plain text, so it can be fixed by hand
+                        if ui
+                            .button("Export Raw")
+                            .on_hover_text("Warning, the exported file isn't encrypted")
+                            .clicked()
+                        {
+                            if let Some(path) = rfd::FileDialog::new()
+                                .set_file_name("keys.json")
+                                .save_file()
+                            {
+                                self.recovery_msg =
+                                    match file::keys::export_raw(&self.encryption_key, &path) {
+                                        Ok(()) => RichText::new("Exported"),
+                                        Err(e) => RichText::new(e.to_string()).color(Color32::RED),
+                                    };
+                            }
+                        }
+
+                        ui.label(self.recovery_msg.clone());
+                    });
+                });
+            }
+
             /// Draw the add tab to the window
             fn draw_add(&mut self, ctx: &egui::Context) {
+                // Image files dropped onto the window are read as QR codes, in the same way as with the Add From QR button
+                let dropped: Vec<_> = ctx.input(|i| {
+                    i.raw
+                        .dropped_files
+                        .iter()
+                        .filter_map(|f| f.path.clone())
+                        .collect()
+                });
+                if !dropped.is_empty() {
+                    // Codes from every dropped file are listed together
+                    self.read_codes(ctx, Ok(qr::parse_files(dropped)));
+                }
+                let hovering = ctx.input(|i| !i.raw.hovered_files.is_empty());
+
+                if self.importing.is_some() {
+                    self.draw_import(ctx);
+                    return;
+                }
+                if self.aegis.is_some() {
+                    self.draw_aegis(ctx);
+                    return;
+                }
+
                 // add_key attribute used to store the state of all manually entered key attributes
                 egui::CentralPanel::default().show(ctx, |ui| {
+                    // Shown while a file is dragged over the window
+                    if hovering {
+                        ui.vertical_centered(|ui| {
+                            ui.label(RichText::new("Drop an image to add its QR code").strong())
+                        });
+                    }
                     ui.horizontal(|ui| {
                         // Secret entry with text box
                         ui.label("Secret");
-                        ui.text_edit_singleline(&mut self.add_key.secret);
-                    });
-                    ui.horizontal(|ui| {
-                        // Name entry with text box
-                        ui.label("Name ");
-                        ui.text_edit_singleline(&mut self.add_key.name);
+                        ui.text_edit_singleline(&mut self.add_secret);
                     });
                     ui.horizontal(|ui| {
-                        // Key type entry with radio buttons
-                        ui.label("Method");
-                        ui.radio_value(&mut self.add_key.options.method, OTPMethod::TOTP, "TOTP");
-                        ui.radio_value(
-                            &mut self.add_key.options.method,
-                            OTPMethod::HOTP(0), // Counter defaults to 0
-                            "HOTP",
-                        );
+                        // Secret encoding entry with radio buttons
+                        ui.label("Encoding");
+                        draw_encoding(ui, &mut self.add_key.encoding);
                     });
+                    // Preview of the secret in canonical form, shown only when formatting will be removed
+                    if let Ok(bytes) = self.add_key.encoding.decode(&self.add_secret) {
+                        let normalised = self.add_key.encoding.encode(&bytes);
+                        if normalised != self.add_secret {
+                            ui.horizontal(|ui| {
+                                ui.label("Normalised");
+                                ui.add(
+                                    egui::Label::new(RichText::new(normalised).monospace().weak())
+                                        .truncate(true),
+                                );
+                            });
+                        }
+                    }
                     ui.horizontal(|ui| {
-                        // Code length entry with radio buttons 4..=6
-                        ui.label("Length  ");
-                        ui.radio_value(&mut self.add_key.options.length, 4, "4");
-                        ui.radio_value(&mut self.add_key.options.length, 5, "5");
-                        ui.radio_value(&mut self.add_key.options.length, 6, "6");
+                        // Issuer entry with text box, which is optional
+                        ui.label("Issuer ");
+                        ui.text_edit_singleline(&mut self.add_key.issuer);
                     });
                     ui.horizontal(|ui| {
-                        // Hash function entry with radio buttons
-                        ui.label("Hash Fn ");
-                        ui.radio_value(&mut self.add_key.options.hash, hash::HashFn::SHA1, "SHA1");
-                        ui.radio_value(
-                            &mut self.add_key.options.hash,
-                            hash::HashFn::SHA256,
-                            "SHA256",
-                        );
-                        ui.radio_value(
-                            &mut self.add_key.options.hash,
-                            hash::HashFn::SHA512,
-                            "SHA512",
-                        );
-                    });
-                    ui.horizontal(|ui| {
-                        // Time interval entry with integer selection
-                        ui.label("Interval");
-                        ui.add(
-                            egui::DragValue::new(&mut self.add_key.options.interval)
-                                .speed(0.2) // Low speed for fine slider control
-                                .clamp_range(10..=300), // Clamped between 10 and 300 incl.
-                        );
+                        // Account name entry with text box
+                        ui.label("Account");
+                        ui.text_edit_singleline(&mut self.add_key.account);
                     });
+                    draw_code_options(ui, &mut self.add_key.options);
                     ui.vertical_centered(|ui| {
                         // Error display for invalid codes, defaults to empty string so isn't shown until an error occurs
                         // Displays in red
@@ -512,47 +1113,324 @@ pub mod ui {
                             self.add_key.time = Utc::now().timestamp();
 
                             // If the key is valid: display and refresh all fields, else: display error to user
-                            if let Err(e) = file::keys::add(&self.add_key, &self.encryption_key) {
-                                self.add_err = e;
-                            } else {
-                                // Generate DisplayKey and Receiver from manually entered key, adding it to the respective data structures stored as attributes in the App
-                                let (key, receiver) = generate_display_key(ctx, &self.add_key);
-                                self.receivers.insert(key.name.clone(), receiver);
-                                self.keys.push(key);
-
-                                // Reset all fields and switch to main tab
-                                self.add_key = Default::default();
-                                self.tab = Tab::Main;
-                                self.add_err = String::new();
-                            }
+                            let added = Key::decode_secret(&self.add_secret, self.add_key.encoding)
+                                .and_then(|secret| {
+                                    self.add_key.secret = secret;
+                                    file::keys::add(&self.add_key, &self.encryption_key)
+                                });
+                            self.finish_add(ctx, added);
                         };
 
                         if ui.button("Add From QR").clicked() {
                             // If QR button just pressed
                             // Allow the user to select a file using their system explorer using rfd crate
                             if let Some(path) = rfd::FileDialog::new().pick_file() {
-                                // Parse QR code with parse fn from qr module - if correct: add it to internal state, if not: show error to user
-                                if let Ok(key) = qr::parse(path) {
-                                    // Make sure the key itself is valid
-                                    if let Err(e) = file::keys::add(&key, &self.encryption_key) {
-                                        self.add_err = e;
-                                    } else {
-                                        // Process key the same way as with manually added key
-                                        let (key, receiver) = generate_display_key(ctx, &key);
-                                        self.receivers.insert(key.name.clone(), receiver);
-                                        self.keys.push(key);
-
-                                        self.add_key = Default::default();
-                                        self.tab = Tab::Main;
-                                        self.add_err = String::new();
+                                // Parse every QR code in the image with parse_all fn from qr module, adding a single key straight away or listing several to choose from
+                                self.read_codes(ctx, qr::parse_all(path));
+                            }
+                        };
+
+                        // Reads QR codes from an image on the clipboard, such as a screenshot, without saving it first
+                        if ui.button("Paste QR").clicked() {
+                            self.read_codes(ctx, qr::parse_clipboard());
+                        }
+
+                        // Reads the keys from an Aegis Authenticator export, which are listed on the import screen
+                        if ui.button("Import Aegis").clicked() {
+                            if let Some(path) = rfd::FileDialog::new()
+                                .add_filter("Aegis export", &["json"])
+                                .pick_file()
+                            {
+                                self.read_aegis(ctx, path);
+                            }
+                        }
+                    });
+
+                    ui.horizontal(|ui| {
+                        // URI entry, for keys given as text rather than as a QR code
+                        ui.label("Paste URI");
+                        ui.add(
+                            egui::TextEdit::singleline(&mut self.add_uri)
+                                .hint_text("otpauth://")
+                                .desired_width(150.),
+                        );
+                        if ui.button("Add URI").clicked() {
+                            // Parsed & validated the same way as a QR code's URI, including Google Authenticator exports
+                            let codes = qr::parse_codes(vec![Ok(self.add_uri.clone())]);
+                            self.read_codes(ctx, Ok(codes));
+                        }
+                    });
+                });
+            }
+
+            /// Handles the keys read from an image's QR codes
+            /// A single key is added straight away, whereas several are listed on the import screen for the user to choose from
+            fn read_codes(&mut self, ctx: &egui::Context, codes: Result<qr::Codes, crate::Error>) {
+                match codes {
+                    Err(e) => self.add_err = e.to_string(),
+                    Ok(mut codes) if codes.len() == 1 => {
+                        let added = codes
+                            .remove(0)
+                            .and_then(|key| file::keys::add(&key, &self.encryption_key));
+                        self.finish_add(ctx, added);
+                    }
+                    Ok(codes) => {
+                        self.add_err = String::new();
+                        self.importing = Some(codes.into_iter().map(ImportItem::new).collect());
+                    }
+                }
+            }
+
+            /// Reads an Aegis export file, asking for its password first if it's encrypted
+            fn read_aegis(&mut self, ctx: &egui::Context, path: std::path::PathBuf) {
+                let text = match std::fs::read_to_string(path) {
+                    Ok(text) => text,
+                    Err(e) => {
+                        self.add_err = crate::Error::from(e).to_string();
+                        return;
+                    }
+                };
+                match file::aegis::is_encrypted(&text) {
+                    Err(e) => self.add_err = e.to_string(),
+                    Ok(true) => {
+                        self.add_err = String::new();
+                        self.aegis = Some(AegisFields {
+                            text,
+                            password: String::new(),
+                        });
+                    }
+                    Ok(false) => self.read_codes(ctx, file::aegis::parse(&text, "")),
+                }
+            }
+
+            /// Draw the password prompt for an encrypted Aegis export to the window, in place of the add tab
+            fn draw_aegis(&mut self, ctx: &egui::Context) {
+                let Some(fields) = &mut self.aegis else {
+                    return;
+                };
+                let (mut unlock, mut cancel) = (false, false);
+
+                egui::CentralPanel::default().show(ctx, |ui| {
+                    ui.label(RichText::new("Encrypted Aegis export").size(20.));
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        ui.label("Password");
+                        ui.add(egui::TextEdit::singleline(&mut fields.password).password(true));
+                    });
+                    ui.vertical_centered(|ui| {
+                        ui.label(RichText::new(&self.add_err).color(Color32::RED))
+                    });
+
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        unlock = ui.button("Unlock").clicked()
+                            || ui.input(|i| i.key_pressed(egui::Key::Enter));
+                        cancel = ui.button("Cancel").clicked();
+                    });
+                });
+
+                if unlock {
+                    // Deriving the key can take a moment, as with the app's own password
+                    match file::aegis::parse(&fields.text, &fields.password) {
+                        // Stays on the prompt, so the password can be entered again
+                        Err(e) => self.add_err = e.to_string(),
+                        Ok(codes) => {
+                            self.aegis = None;
+                            self.read_codes(ctx, Ok(codes));
+                        }
+                    }
+                } else if cancel {
+                    self.aegis = None;
+                    self.add_err = String::new();
+                }
+            }
+
+            /// Draw the import screen to the window, in place of the add tab
+            /// Lists every key read from an image with several QR codes or from an export, so the user can choose which to add
+            fn draw_import(&mut self, ctx: &egui::Context) {
+                let Some(items) = &mut self.importing else {
+                    return;
+                };
+                let (mut import, mut done) = (false, false);
+
+                egui::CentralPanel::default().show(ctx, |ui| {
+                    ui.label(RichText::new(format!("{} keys found", items.len())).size(20.));
+                    ui.separator();
+
+                    egui::ScrollArea::vertical()
+                        .max_height(200.)
+                        .show(ui, |ui| {
+                            for item in items.iter_mut() {
+                                match &item.key {
+                                    // Keys that have been added can't be selected again
+                                    Ok(key) => {
+                                        ui.add_enabled(
+                                            !item.added,
+                                            egui::Checkbox::new(&mut item.selected, key.label()),
+                                        );
+                                    }
+                                    Err(e) => {
+                                        ui.label(
+                                            RichText::new(format!("Unreadable key: {}", e))
+                                                .color(Color32::RED),
+                                        );
                                     }
-                                } else {
-                                    self.add_err = String::from("Could not parse QR code")
+                                }
+                                if !item.status.is_empty() {
+                                    ui.label(item.status.clone());
                                 }
                             }
-                        };
+                        });
+                    ui.vertical_centered(|ui| {
+                        ui.label(RichText::new(&self.add_err).color(Color32::RED))
+                    });
+
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        let any = items.iter().any(|i| i.selected);
+                        import = ui
+                            .add_enabled(any, egui::Button::new("Import Selected"))
+                            .clicked();
+                        done = ui.button("Done").clicked();
                     });
                 });
+
+                if import {
+                    self.import_selected(ctx);
+                } else if done {
+                    // Shows the imported keys, if there are any
+                    if items.iter().any(|i| i.added) {
+                        self.tab = Tab::Main;
+                    }
+                    self.importing = None;
+                    self.add_err = String::new();
+                }
+            }
+
+            /// Adds the keys selected on the import screen to the key file, showing whether each one was added
+            fn import_selected(&mut self, ctx: &egui::Context) {
+                let Some(items) = &mut self.importing else {
+                    return;
+                };
+                // Indices of the selected keys, to match them up with their results
+                let selected: Vec<usize> = (0..items.len())
+                    .filter(|&i| items[i].selected && items[i].key.is_ok())
+                    .collect();
+                let keys: Vec<Key> = selected
+                    .iter()
+                    .filter_map(|&i| items[i].key.clone().ok())
+                    .collect();
+
+                match file::keys::import(&keys, &self.encryption_key) {
+                    Err(e) => self.add_err = e.to_string(),
+                    Ok(results) => {
+                        self.add_err = String::new();
+                        for (i, result) in selected.into_iter().zip(results) {
+                            let item = &mut items[i];
+                            item.selected = false;
+                            match result {
+                                Ok(stored) => {
+                                    // Process key the same way as with manually added key
+                                    let (key, receiver) =
+                                        generate_display_key(ctx, &stored, &self.clock);
+                                    self.receivers.insert(key.id, receiver);
+                                    self.keys.push(key);
+                                    item.added = true;
+                                    item.status = RichText::new("Added");
+                                }
+                                Err(e) => {
+                                    item.status = RichText::new(e.to_string()).color(Color32::RED)
+                                }
+                            }
+                        }
+                        self.keys = sort_keys(self.keys.clone(), &self.options.sort);
+                    }
+                }
+            }
+
+            /// Displays a key that has just been stored, then resets all add tab fields and switches to the main tab
+            /// If the key couldn't be added, the error is shown to the user instead
+            fn finish_add(&mut self, ctx: &egui::Context, added: Result<Key, crate::Error>) {
+                match added {
+                    Err(e) => self.add_err = e.to_string(),
+                    Ok(stored) => {
+                        // Generate DisplayKey and Receiver from the stored key, adding it to the respective data structures stored as attributes in the App
+                        let (key, receiver) = generate_display_key(ctx, &stored, &self.clock);
+                        self.receivers.insert(key.id, receiver);
+                        self.keys.push(key);
+
+                        // Reset all fields and switch to main tab
+                        self.add_key = Default::default();
+                        self.add_secret = String::new();
+                        self.add_uri = String::new();
+                        self.tab = Tab::Main;
+                        self.add_err = String::new();
+                    }
+                }
+            }
+
+            /// Draw the OCRA tab to the window
+            /// Only the inputs used by the entered suite are shown
+            fn draw_ocra(&mut self, ctx: &egui::Context) {
+                egui::CentralPanel::default().show(ctx, |ui| {
+                    let fields = &mut self.ocra;
+                    ui.horizontal(|ui| {
+                        ui.label("Secret   ");
+                        ui.text_edit_singleline(&mut fields.secret);
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Encoding ");
+                        draw_encoding(ui, &mut fields.encoding);
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Suite    ");
+                        ui.text_edit_singleline(&mut fields.suite);
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Challenge");
+                        ui.text_edit_singleline(&mut fields.challenge);
+                    });
+
+                    // Optional inputs, depending on the fields in the suite
+                    if let Ok(suite) = fields.suite.parse::<ocra::Suite>() {
+                        if suite.counter {
+                            ui.horizontal(|ui| {
+                                ui.label("Counter  ");
+                                ui.add(egui::DragValue::new(&mut fields.counter));
+                            });
+                        }
+                        if suite.pin.is_some() {
+                            ui.horizontal(|ui| {
+                                ui.label("PIN      ");
+                                ui.add(egui::TextEdit::singleline(&mut fields.pin).password(true));
+                            });
+                        }
+                        if suite.session.is_some() {
+                            ui.horizontal(|ui| {
+                                ui.label("Session  ");
+                                ui.text_edit_singleline(&mut fields.session)
+                                    .on_hover_text("Session information, in hexadecimal");
+                            });
+                        }
+                    }
+
+                    ui.vertical_centered(|ui| {
+                        // Error display, in red
+                        ui.label(RichText::new(&fields.err).color(Color32::RED))
+                    });
+
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        if ui.button("Respond").clicked() {
+                            fields.respond(&*self.clock);
+                        }
+                        // Response can be copied once generated
+                        if !fields.response.is_empty() && ui.button("Copy").clicked() {
+                            ui.output_mut(|o| o.copied_text = fields.response.clone());
+                        }
+                    });
+                    ui.label(RichText::new(&fields.response).size(30.).strong());
+                });
             }
 
             /// Draw the options tab to the window
@@ -566,12 +1444,15 @@ pub mod ui {
                             .radio_value(&mut self.options.sort, SortBy::Date, "Time Added")
                             .clicked()
                             || ui
-                                .radio_value(&mut self.options.sort, SortBy::Name, "Name")
+                                .radio_value(&mut self.options.sort, SortBy::Issuer, "Issuer")
+                                .clicked()
+                            || ui
+                                .radio_value(&mut self.options.sort, SortBy::Account, "Account")
                                 .clicked()
                         {
-                            // If either option selected, refresh the keys with the new sorting choice and save the choice to the settings file
+                            // If any option selected, refresh the keys with the new sorting choice and save the choice to the settings file
                             self.keys = sort_keys(self.keys.clone(), &self.options.sort);
-                            file::options::save(&self.options)
+                            self.save_options()
                         }
                     });
                     ui.horizontal(|ui| {
@@ -583,7 +1464,7 @@ pub mod ui {
                             .clicked()
                         {
                             // If changed, save choice to settings file
-                            file::options::save(&self.options)
+                            self.save_options()
                         }
                     });
                     ui.horizontal(|ui| {
@@ -593,9 +1474,49 @@ pub mod ui {
                             .clicked_elsewhere()
                         {
                             self.update_accent(ctx);
-                            file::options::save(&self.options)
+                            self.save_options()
                         }
-                    })
+                    });
+                    ui.horizontal(|ui| {
+                        // Time offset option, for when the system clock has drifted
+                        // The code at a particular time is shown from the edit screen instead
+                        ui.label("Time Offset");
+                        let response = ui.add(
+                            egui::DragValue::new(&mut self.options.time_offset)
+                                .speed(0.2)
+                                .clamp_range(-3600..=3600) // Clamped to an hour either way
+                                .suffix("s"),
+                        );
+                        // Only applied once the user has finished changing the value, as every thread needs to be restarted
+                        if response.drag_stopped() || response.lost_focus() {
+                            self.clock = self.options.clock();
+                            self.reload_keys(ctx);
+                            self.save_options()
+                        }
+                    });
+                    ui.vertical_centered(|ui| {
+                        // Error display for when settings can't be saved, empty by default
+                        ui.label(RichText::new(&self.options_err).color(Color32::RED))
+                    });
+
+                    ui.separator();
+
+                    // Password section, which re-encrypts the key file without losing any stored keys
+                    ui.label("Change Password");
+                    self.password.draw(ui);
+                    ui.horizontal(|ui| {
+                        if ui.button("Change").clicked() {
+                            self.password_msg = match self.password.change() {
+                                Ok(e_key) => {
+                                    // Threads are sent the key with each message, so only the app's copy needs replacing
+                                    self.encryption_key = e_key;
+                                    RichText::new("Password changed")
+                                }
+                                Err(e) => RichText::new(e).color(Color32::RED),
+                            };
+                        }
+                        ui.label(self.password_msg.clone());
+                    });
                 });
             }
 
@@ -626,12 +1547,99 @@ pub mod ui {
                 });
             }
         }
+
+        #[cfg(test)]
+        mod tests {
+            use super::*;
+
+            /// Creates a TOTP [DisplayKey] with a given code, without a thread attached
+            fn display_key(code: &str) -> DisplayKey {
+                let (sender, _) = std::sync::mpsc::channel();
+                let mut key = DisplayKey::new(
+                    0,
+                    String::new(),
+                    String::new(),
+                    String::new(),
+                    sender,
+                    0,
+                    OTPMethodStripped::TOTP,
+                );
+                key.code = String::from(code);
+                key
+            }
+
+            /// Creates a [DisplayKey] with a given issuer & account name
+            fn named_key(issuer: &str, account: &str) -> DisplayKey {
+                let mut key = display_key("");
+                key.issuer = String::from(issuer);
+                key.account = String::from(account);
+                key
+            }
+
+            #[test]
+            fn search() {
+                let key = named_key("GitHub", "primm@example.com");
+                assert!(key.matches(""));
+                assert!(key.matches("git"));
+                assert!(key.matches("PRIMM"));
+                assert!(!key.matches("gitlab"));
+
+                // Groups are searched too
+                let mut key = named_key("GitHub", "primm@example.com");
+                key.group = String::from("Work");
+                assert!(key.matches("work"));
+            }
+
+            #[test]
+            fn sort_names() {
+                let keys = vec![named_key("b", "a"), named_key("", "c"), named_key("A", "b")];
+                let names = |keys: Vec<DisplayKey>| -> Vec<String> {
+                    keys.into_iter().map(|k| k.account).collect()
+                };
+                assert_eq!(
+                    names(sort_keys(keys.clone(), &SortBy::Issuer)),
+                    ["c", "b", "a"]
+                );
+                assert_eq!(names(sort_keys(keys, &SortBy::Account)), ["a", "b", "c"]);
+            }
+
+            #[test]
+            fn code_string_no_spacer() {
+                assert_eq!(display_key("002082").generate_code_string(false), "002082");
+            }
+
+            #[test]
+            fn code_string_even() {
+                assert_eq!(display_key("002082").generate_code_string(true), "002 082");
+                assert_eq!(
+                    display_key("12345678").generate_code_string(true),
+                    "1234 5678"
+                );
+                assert_eq!(
+                    display_key("1284755224").generate_code_string(true),
+                    "12847 55224"
+                );
+            }
+
+            #[test]
+            fn code_string_odd() {
+                assert_eq!(display_key("12345").generate_code_string(true), "12345");
+                assert_eq!(display_key("PV9M4").generate_code_string(true), "PV9M4");
+                assert_eq!(
+                    display_key("1234567").generate_code_string(true),
+                    "123 4567"
+                );
+                assert_eq!(
+                    display_key("123456789").generate_code_string(true),
+                    "123 456 789"
+                );
+            }
+        }
     }
 
     /// Handles the initial password window
     pub mod password {
         use std::cell::RefCell;
-        use std::path::Path;
         use std::rc::Rc;
 
         use super::*;
@@ -678,8 +1686,8 @@ pub mod ui {
             // No chance of panicing, as this code is run after app is dropped, so satisfies concurrent mutable references rule
             let out_ref_c = encryption_key.borrow();
 
-            // Return Option<e_key> by dereference
-            Ok(*out_ref_c)
+            // Return a clone of the Option<e_key>
+            Ok(out_ref_c.clone())
         }
 
         /// Struct that handles the password window & its stored data
@@ -688,11 +1696,17 @@ pub mod ui {
             encryption_key: Rc<RefCell<Option<EncryptionKey>>>, // Allows for the string to have multiple references + be interior mutable
             password_field: String,
             error: String,
+            changing: Option<PasswordFields>, // Set when changing the password, replacing the usual password entry
         }
 
         impl eframe::App for App {
             /// Called every frame to update the winow
             fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+                if self.changing.is_some() {
+                    self.draw_change(ctx);
+                    return;
+                }
+
                 egui::CentralPanel::default().show(ctx, |ui| {
                     ui.label("Please enter a password"); // Label
                     let text_field = ui.add(
@@ -718,52 +1732,47 @@ pub mod ui {
                         {
                             // Logic for when enter button clicked (or enter pressed)
 
-                            // Calculate encryption key from
-                            let e_key = encrypt::password_to_key(&self.password_field);
-
-                            let path = crate::file::get_dir().join(Path::new(crate::file::KEYPATH));
-
-                            // If the keys file doesn't exist, create it
-                            if !path.try_exists().unwrap() {
-                                if let Err(_) = file::keys::new_file(&e_key) {
-                                    self.error = String::from("An error occurred")
+                            // Derive the encryption key from the password, creating the key file if it doesn't exist, and checking the password can decrypt it
+                            match file::keys::unlock(&self.password_field) {
+                                // If password incorrect, the error box of the GUI displays incorrect password, otherwise it displays the reason
+                                Err(e) => self.error = e.to_string(),
+                                Ok(e_key) => {
+                                    // If the password is correct, mutably deref the encryption key attribute and assign the derived key to it
+                                    *(*self.encryption_key).borrow_mut() = Some(e_key);
+                                    // Close the window, allowing gui fn to continue
+                                    ctx.send_viewport_cmd(egui::ViewportCommand::Close)
                                 }
                             }
-
-                            if let Err(e) = encrypt::load(&path, &e_key) {
-                                // If password incorrect
-                                self.error =
-                                    if let Some(&encrypt::Error::ReadError) = e.downcast_ref() {
-                                        // Downcast converts generic to concrete type
-                                        // If error returned is ReadError, set the error box of the GUI to display incorrect password
-                                        String::from("Incorrect password")
-                                    } else {
-                                        // If error returned isn't ReadError , set the error box of the GUI to display generic error message
-                                        String::from("An error occurred")
-                                    }
-                            } else {
-                                // If the password is correct, mutably deref the encryption key attribute and assign the previously calculated key to it
-                                *(*self.encryption_key).borrow_mut() = Some(e_key);
-                                // Close the window, allowing gui fn to continue
-                                ctx.send_viewport_cmd(egui::ViewportCommand::Close)
-                            }
                         }
                         let response = ui
                             .button("Set as new password")
                             .on_hover_text("Warning, this will delete all currently stored codes"); // Tooltip
                         if response.clicked() {
-                            // If reset password button pressed, deletes all old codes so new key can be used
-                            if let Err(_) = file::keys::new_file(&encrypt::password_to_key(
-                                &self.password_field,
-                            )) {
-                                self.error = String::from("An error occurred")
-                            } else {
-                                // Sets the key attribute and closes the window, as with enter button
-                                *(*self.encryption_key).borrow_mut() =
-                                    Some(encrypt::password_to_key(&self.password_field));
-                                ctx.send_viewport_cmd(egui::ViewportCommand::Close)
+                            // If reset password button pressed, deletes all old codes so a new key (with a new salt) can be used
+                            let reset = EncryptionKey::new(&self.password_field)
+                                .map_err(crate::Error::from)
+                                .and_then(|e_key| file::keys::new_file(&e_key).map(|_| e_key));
+                            match reset {
+                                Err(_) => self.error = String::from("An error occurred"),
+                                Ok(e_key) => {
+                                    // Sets the key attribute and closes the window, as with enter button
+                                    *(*self.encryption_key).borrow_mut() = Some(e_key);
+                                    ctx.send_viewport_cmd(egui::ViewportCommand::Close)
+                                }
                             }
                         }
+                        if ui.button("Change password").clicked() {
+                            // Switch to the change password fields, carrying over anything already entered as the current password
+                            self.changing = Some(PasswordFields {
+                                current: std::mem::take(&mut self.password_field),
+                                ..Default::default()
+                            });
+                            self.error = String::new();
+                            // Window grows to fit the extra fields
+                            ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(egui::vec2(
+                                320., 208.,
+                            )));
+                        }
                     })
                 });
             }
@@ -781,6 +1790,48 @@ pub mod ui {
                     encryption_key,
                     password_field: String::new(),
                     error: String::new(),
+                    changing: None,
+                }
+            }
+
+            /// Draws the fields to change the password, used in place of the password entry
+            /// Unlike setting a new password, all stored codes are kept
+            fn draw_change(&mut self, ctx: &egui::Context) {
+                let Some(fields) = &mut self.changing else {
+                    return;
+                };
+                let mut back = false;
+
+                egui::CentralPanel::default().show(ctx, |ui| {
+                    ui.label("Please enter your current and new password");
+                    fields.draw(ui);
+
+                    ui.vertical_centered(|ui| {
+                        ui.label(RichText::new(&self.error).color(Color32::RED))
+                    });
+                    ui.separator();
+
+                    ui.horizontal(|ui| {
+                        if ui.button("Change password").clicked()
+                            || ui.input(|i| i.key_pressed(egui::Key::Enter))
+                        {
+                            match fields.change() {
+                                Err(e) => self.error = e,
+                                Ok(e_key) => {
+                                    // Opens the main window with the new key, as with the enter button
+                                    *(*self.encryption_key).borrow_mut() = Some(e_key);
+                                    ctx.send_viewport_cmd(egui::ViewportCommand::Close)
+                                }
+                            }
+                        }
+                        back = ui.button("Back").clicked();
+                    })
+                });
+
+                if back {
+                    self.changing = None;
+                    self.error = String::new();
+                    ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(egui::vec2(320., 160.)));
                 }
             }
 
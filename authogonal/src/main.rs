@@ -1,9 +1,22 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release profile
 
 use authogonal::*;
+use std::process::ExitCode;
 
 // Executed when the program is run, with all errors propegated through the function using the ? operator
-fn main() -> Result<(), eframe::Error> {
+fn main() -> Result<ExitCode, eframe::Error> {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    // The data directory applies to both the GUI & command line, so is taken out before choosing which to run
+    if let Some(dir) = cli::take_data_dir(&mut args) {
+        file::set_data_dir(dir);
+    }
+
+    // If any other arguments are given, run the command line interface instead of opening any windows
+    if !args.is_empty() {
+        attach_console();
+        return Ok(cli::run(args));
+    }
+
     // Create & display the password window
     let e_key = ui::password::gui()?;
     // If there is a key (the user entered their password), create the main window
@@ -13,5 +26,25 @@ fn main() -> Result<(), eframe::Error> {
     }
 
     // Return success
-    Ok(())
+    Ok(ExitCode::SUCCESS)
 }
+
+/// Attaches to the console of the shell the app was run from, as release builds on Windows don't have one of their own
+/// Without this, output & the password prompt would go nowhere
+#[cfg(windows)]
+fn attach_console() {
+    // Parent process ID value, from wincon.h
+    const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn AttachConsole(process_id: u32) -> i32;
+    }
+    // Fails if the app already has a console (as in debug builds) or wasn't run from one, neither of which can be helped
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+/// Other platforms always have the shell's console
+#[cfg(not(windows))]
+fn attach_console() {}
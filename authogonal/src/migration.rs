@@ -0,0 +1,411 @@
+// Handles the QR codes exported by Google Authenticator's transfer accounts feature
+// These contain an otpauth-migration://offline?data=DATA URI, where DATA is a base 64 protocol buffer holding several keys
+// Large exports are split into batches, with each QR code holding one batch
+
+use crate::encoding;
+use crate::key::{CodeOptions, Key};
+use crate::otp::OTPMethod;
+use crate::uri;
+use hash::HashFn;
+
+/// Scheme used by export URIs, which is matched ignoring case
+const SCHEME: &str = "otpauth-migration://";
+/// Most batches an export can be split into, well above the number Google Authenticator creates, so a crafted size can't be used to report millions of missing batches
+const MAX_BATCHES: u32 = 100;
+
+/// The keys held by a single export QR code
+#[derive(Debug)]
+pub struct Batch {
+    pub keys: Vec<Result<Key, Error>>, // Keys that use unsupported options are kept as errors, so the rest can still be imported
+    pub index: u32,                    // Position of this batch in the export, starting from 0
+    pub size: u32,                     // Number of batches in the export
+    pub id: i32,                       // Shared by every batch in the same export
+}
+
+/// Whether text is an export URI, rather than a URI for a single key
+pub fn is_migration(text: &str) -> bool {
+    text.trim()
+        .get(..SCHEME.len())
+        .is_some_and(|s| s.eq_ignore_ascii_case(SCHEME))
+}
+
+/// Parses an export URI into the batch of keys it holds
+pub fn parse(text: &str) -> Result<Batch, Error> {
+    if !is_migration(text) {
+        return Err(Error::Uri);
+    }
+    let (_, query) = text.trim().split_once('?').ok_or(Error::Uri)?;
+
+    // Only the data parameter is used
+    let data = query
+        .split('&')
+        .find_map(|p| p.strip_prefix("data="))
+        .ok_or(Error::Uri)?;
+    let data = uri::decode(data).map_err(|_| Error::Encoding)?;
+    // A + in the data may have already been decoded as a space, so is changed back
+    let payload = encoding::base64::decode(&data.replace(' ', "+")).map_err(|_| Error::Encoding)?;
+
+    parse_payload(&payload)
+}
+
+/// Parses the payload message, which holds the keys followed by details of the batch
+fn parse_payload(data: &[u8]) -> Result<Batch, Error> {
+    let mut batch = Batch {
+        keys: Vec::new(),
+        index: 0,
+        size: 1,
+        id: 0,
+    };
+
+    let mut reader = Reader::new(data);
+    while let Some((number, value)) = reader.field()? {
+        match (number, value) {
+            (1, Value::Bytes(key)) => batch.keys.push(parse_key(key)?),
+            // Older exports don't split keys into batches, so have no size
+            (3, Value::Varint(size)) => {
+                batch.size = u32::try_from(size).map_err(|_| Error::Payload)?.max(1)
+            }
+            (4, Value::Varint(index)) => {
+                batch.index = u32::try_from(index).map_err(|_| Error::Payload)?
+            }
+            // ID is a signed 32 bit integer, which protocol buffers extend to 64 bits when negative
+            (5, Value::Varint(id)) => batch.id = id as i32,
+            // Version & any unknown fields are skipped
+            _ => (),
+        }
+    }
+
+    if batch.size > MAX_BATCHES || batch.index >= batch.size {
+        return Err(Error::Payload);
+    }
+    Ok(batch)
+}
+
+/// Parses a single key's message, which is only an error if the message itself is invalid
+/// A key with options that aren't supported is returned as an inner error
+fn parse_key(data: &[u8]) -> Result<Result<Key, Error>, Error> {
+    let mut secret = Vec::new();
+    let mut name = String::new();
+    let mut issuer = String::new();
+    let (mut algorithm, mut digits, mut kind, mut counter) = (0, 0, 0, 0);
+
+    let mut reader = Reader::new(data);
+    while let Some((number, value)) = reader.field()? {
+        match (number, value) {
+            (1, Value::Bytes(bytes)) => secret = bytes.to_vec(),
+            (2, Value::Bytes(bytes)) => name = text(bytes)?,
+            (3, Value::Bytes(bytes)) => issuer = text(bytes)?,
+            (4, Value::Varint(v)) => algorithm = v,
+            (5, Value::Varint(v)) => digits = v,
+            (6, Value::Varint(v)) => kind = v,
+            (7, Value::Varint(v)) => counter = v,
+            _ => (),
+        }
+    }
+
+    // Unspecified values are given the usual defaults
+    let hash = match algorithm {
+        0 | 1 => HashFn::SHA1,
+        2 => HashFn::SHA256,
+        3 => HashFn::SHA512,
+        4 => return Ok(Err(Error::Unsupported(String::from("MD5")))),
+        _ => return Ok(Err(Error::Unsupported(String::from("algorithm")))),
+    };
+    let length = match digits {
+        0 | 1 => 6,
+        2 => 8,
+        _ => return Ok(Err(Error::Unsupported(String::from("code length")))),
+    };
+    let method = match kind {
+        0 | 2 => OTPMethod::TOTP,
+        1 => OTPMethod::HOTP(counter),
+        _ => return Ok(Err(Error::Unsupported(String::from("key type")))),
+    };
+
+    // Name is usually a label in the same form as a key URI's
+    let issuer = (!issuer.is_empty()).then_some(issuer);
+    let (issuer, account) = uri::parse_label(&name, issuer);
+    Ok(Ok(Key::new(
+        secret,
+        issuer,
+        account,
+        CodeOptions::new(method, hash, length, 30), // Exports don't include the interval, as it's always 30 seconds
+    )))
+}
+
+/// Decodes a string field, which must be UTF-8
+fn text(bytes: &[u8]) -> Result<String, Error> {
+    String::from_utf8(bytes.to_vec()).map_err(|_| Error::Payload)
+}
+
+/// Finds the batches missing from a set of batches, as (index, size) pairs
+/// Batches are grouped by their export's ID, so several exports can be checked at once
+pub fn missing(batches: &[Batch]) -> Vec<(u32, u32)> {
+    let mut missing = Vec::new();
+    let mut checked = Vec::new();
+    for batch in batches {
+        if checked.contains(&batch.id) {
+            continue;
+        }
+        checked.push(batch.id);
+
+        let export: Vec<_> = batches.iter().filter(|b| b.id == batch.id).collect();
+        // Sizes are limited when parsed, but batches can also be built directly
+        for index in 0..batch.size.min(MAX_BATCHES) {
+            if !export.iter().any(|b| b.index == index) {
+                missing.push((index, batch.size));
+            }
+        }
+    }
+    missing
+}
+
+/// A field's value, as either of the wire types used by the export format
+#[derive(Debug, PartialEq)]
+enum Value<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+}
+
+/// Reads fields from a protocol buffer message in order
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    /// Reads the next field's number & value, or None at the end of the message
+    /// Fixed size fields aren't used by the export format, so are skipped
+    fn field(&mut self) -> Result<Option<(u64, Value<'a>)>, Error> {
+        loop {
+            if self.pos == self.data.len() {
+                return Ok(None);
+            }
+            // The key's lowest 3 bits are the wire type, and the rest the field number
+            let key = self.varint()?;
+            let number = key >> 3;
+            match key & 7 {
+                0 => return Ok(Some((number, Value::Varint(self.varint()?)))),
+                1 => {
+                    self.take(8)?;
+                }
+                2 => {
+                    let len = self.varint()?;
+                    let len = usize::try_from(len).map_err(|_| Error::Payload)?;
+                    return Ok(Some((number, Value::Bytes(self.take(len)?))));
+                }
+                5 => {
+                    self.take(4)?;
+                }
+                _ => return Err(Error::Payload),
+            }
+        }
+    }
+
+    /// Reads a variable length integer, stored 7 bits at a time from least significant, with the top bit set on all but the last byte
+    fn varint(&mut self) -> Result<u64, Error> {
+        let mut value = 0;
+        // 64 bits fit within 10 bytes
+        for i in 0..10 {
+            let byte = *self.data.get(self.pos).ok_or(Error::Payload)?;
+            self.pos += 1;
+            value |= ((byte & 0x7f) as u64) << (7 * i);
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(Error::Payload)
+    }
+
+    /// Takes the next bytes, failing if the message is too short
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let end = self.pos.checked_add(len).ok_or(Error::Payload)?;
+        let bytes = self.data.get(self.pos..end).ok_or(Error::Payload)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+}
+
+/// Error enum to handle errors with export parsing
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    Uri,                    // URI isn't an export URI with a data parameter
+    Encoding,               // Data isn't valid percent-encoded base 64
+    Payload,                // Data isn't a valid export
+    Unsupported(String),    // Key uses an option that can't be generated, with the option's name
+    MissingBatch(u32, u32), // A batch of the export wasn't read, with its index & the number of batches
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Uri => write!(f, "Not a Google Authenticator export"),
+            Self::Encoding | Self::Payload => write!(f, "Google Authenticator export is invalid"),
+            Self::Unsupported(option) => write!(f, "Unsupported {}", option),
+            Self::MissingBatch(index, size) => write!(
+                f,
+                "QR code {} of {} from the export is missing",
+                index + 1,
+                size
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Encodes a varint, for building messages
+    fn varint(mut value: u64) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        while value >= 0x80 {
+            bytes.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        bytes.push(value as u8);
+        bytes
+    }
+
+    /// Encodes a varint field
+    fn int(number: u64, value: u64) -> Vec<u8> {
+        [varint(number << 3), varint(value)].concat()
+    }
+
+    /// Encodes a length delimited field
+    fn bytes(number: u64, value: &[u8]) -> Vec<u8> {
+        [
+            varint(number << 3 | 2),
+            varint(value.len() as u64),
+            value.to_vec(),
+        ]
+        .concat()
+    }
+
+    /// Encodes a payload as an export URI, percent-encoding the base 64 characters that need it
+    fn export(payload: &[u8]) -> String {
+        let data = encoding::base64::encode(payload)
+            .replace('+', "%2B")
+            .replace('/', "%2F")
+            .replace('=', "%3D");
+        format!("otpauth-migration://offline?data={}", data)
+    }
+
+    fn google_key() -> Vec<u8> {
+        [
+            bytes(1, b"Hello!\xde\xad\xbe\xef"),
+            bytes(2, b"Example:alice@google.com"),
+            bytes(3, b"Example"),
+            int(4, 1),
+            int(5, 1),
+            int(6, 2),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn single() {
+        let payload = [bytes(1, &google_key()), int(2, 1), int(3, 1)].concat();
+        let batch = parse(&export(&payload)).unwrap();
+        assert_eq!((batch.index, batch.size), (0, 1));
+        assert_eq!(
+            batch.keys,
+            [Ok(Key::new(
+                b"Hello!\xde\xad\xbe\xef".to_vec(),
+                String::from("Example"),
+                String::from("alice@google.com"),
+                Default::default()
+            ))]
+        );
+    }
+
+    #[test]
+    fn options() {
+        let hotp = [
+            bytes(1, b"12345678901234567890"),
+            bytes(2, b"bob"),
+            int(4, 2),
+            int(5, 2),
+            int(6, 1),
+            int(7, 20),
+        ]
+        .concat();
+        let md5 = [bytes(1, b"1"), bytes(2, b"carol"), int(4, 4)].concat();
+        // Fixed size fields & unknown fields are skipped
+        let unknown = [vec![0x45, 1, 2, 3, 4], int(15, 1)].concat();
+        let payload = [bytes(1, &hotp), bytes(1, &md5), unknown].concat();
+
+        let batch = parse_payload(&payload).unwrap();
+        let key = batch.keys[0].as_ref().unwrap();
+        assert_eq!(key.issuer, "");
+        assert_eq!(key.account, "bob");
+        assert_eq!(
+            key.options,
+            CodeOptions::new(OTPMethod::HOTP(20), HashFn::SHA256, 8, 30)
+        );
+        // Unsupported keys don't stop the others being read
+        assert_eq!(batch.keys[1], Err(Error::Unsupported(String::from("MD5"))));
+    }
+
+    #[test]
+    fn batches() {
+        let batch = |index, id: i32| {
+            let payload = [
+                bytes(1, &google_key()),
+                int(3, 3),
+                int(4, index),
+                int(5, id as i64 as u64),
+            ]
+            .concat();
+            parse(&export(&payload)).unwrap()
+        };
+        let read = [batch(0, -5), batch(2, -5), batch(1, 7)];
+        assert_eq!(read[0].id, -5);
+        assert_eq!(missing(&read), [(1, 3), (0, 3), (2, 3)]);
+        assert!(missing(&[batch(0, 1), batch(1, 1), batch(2, 1)]).is_empty());
+    }
+
+    #[test]
+    fn invalid() {
+        assert_eq!(parse("otpauth://totp/a?data=AA").unwrap_err(), Error::Uri);
+        assert_eq!(
+            parse("otpauth-migration://offline?data=%").unwrap_err(),
+            Error::Encoding
+        );
+        assert_eq!(
+            parse("otpauth-migration://offline?data=!!!!").unwrap_err(),
+            Error::Encoding
+        );
+        // Truncated length delimited field
+        assert_eq!(
+            parse_payload(&[0x0a, 0x05, 0x01]).unwrap_err(),
+            Error::Payload
+        );
+        // Unterminated varint
+        assert_eq!(parse_payload(&[0x10, 0x80]).unwrap_err(), Error::Payload);
+        // Batch index outside of the export
+        assert_eq!(
+            parse_payload(&[int(3, 2), int(4, 2)].concat()).unwrap_err(),
+            Error::Payload
+        );
+        // Size too large to be a real export, or to fit in 32 bits
+        assert_eq!(
+            parse_payload(&[0x18, 0xff, 0xff, 0xff, 0xff, 0x0f]).unwrap_err(),
+            Error::Payload
+        );
+        assert_eq!(parse_payload(&int(3, 1 << 32)).unwrap_err(), Error::Payload);
+    }
+
+    #[test]
+    fn scheme() {
+        assert!(is_migration(" OTPAUTH-MIGRATION://offline?data="));
+        assert!(!is_migration("otpauth://totp/a"));
+        assert!(!is_migration("otp"));
+    }
+}
@@ -0,0 +1,514 @@
+// Handles OCRA challenge-response codes, as specified in RFC 6287
+// OCRA codes are generated with the same HMAC truncation as HOTP codes, but from a message built from a challenge and other optional inputs
+
+use crate::encoding::hex;
+use crate::hmac;
+use crate::otp::truncate;
+use hash::HashFn;
+
+/// Size in bytes that the challenge is padded to in the data input
+const CHALLENGE_SIZE: usize = 128;
+
+/// Format that a challenge is entered in, as defined by the suite's Q field
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChallengeFormat {
+    Numeric,      // Decimal digits (N)
+    Alphanumeric, // ASCII characters (A)
+    Hex,          // Hexadecimal digits (H)
+}
+
+/// A parsed OCRA suite string (e.g. OCRA-1:HOTP-SHA256-8:QN08-PSHA1), describing how a response is calculated
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Suite {
+    text: String, // Original suite string, as it is included in the data input
+    pub hash: HashFn,
+    pub digits: u8, // 0 means the response isn't truncated
+    pub counter: bool,
+    pub challenge_format: ChallengeFormat,
+    pub challenge_len: usize, // Nominal challenge length, exceeded in mutual challenge-response where 2 challenges are combined
+    pub pin: Option<HashFn>,  // Hash function the PIN is hashed with, if used
+    pub session: Option<usize>, // Length of session information in bytes, if used
+    pub timestep: Option<u64>, // Timestep in seconds, if used
+}
+
+impl std::str::FromStr for Suite {
+    type Err = Error;
+
+    /// Parses a suite string of the form OCRA-1:HOTP-[hash]-[digits]:[data input]
+    fn from_str(suite: &str) -> Result<Self, Self::Err> {
+        // Suite is made up of 3 colon separated sections - version, crypto function and data input
+        let mut sections = suite.split(':');
+        let (Some("OCRA-1"), Some(function), Some(input), None) = (
+            sections.next(),
+            sections.next(),
+            sections.next(),
+            sections.next(),
+        ) else {
+            return Err(Error::InvalidSuite);
+        };
+
+        // Crypto function is of the form HOTP-[hash]-[digits]
+        let mut function = function.split('-');
+        let (Some("HOTP"), Some(hash), Some(digits), None) = (
+            function.next(),
+            function.next(),
+            function.next(),
+            function.next(),
+        ) else {
+            return Err(Error::InvalidSuite);
+        };
+        let hash = parse_hash(hash)?;
+        let digits: u8 = digits.parse().map_err(|_| Error::InvalidSuite)?;
+        if digits != 0 && !(4..=10).contains(&digits) {
+            return Err(Error::InvalidSuite);
+        }
+
+        // Data input is a dash separated list of fields, in the order [C]-QFxx-[PH|Snnn|TG]
+        let mut fields = input.split('-').peekable();
+
+        // Optional counter
+        let counter = fields.next_if_eq(&"C").is_some();
+
+        // Required challenge field, of the form QFxx
+        let challenge = fields.next().ok_or(Error::InvalidSuite)?;
+        let challenge = challenge.strip_prefix('Q').ok_or(Error::InvalidSuite)?;
+        let challenge_format = match challenge.get(..1) {
+            Some("N") => ChallengeFormat::Numeric,
+            Some("A") => ChallengeFormat::Alphanumeric,
+            Some("H") => ChallengeFormat::Hex,
+            _ => return Err(Error::InvalidSuite),
+        };
+        let challenge_len: usize = challenge[1..].parse().map_err(|_| Error::InvalidSuite)?;
+        if !(4..=64).contains(&challenge_len) {
+            return Err(Error::InvalidSuite);
+        }
+
+        // Remaining optional fields, identified by their first character
+        let (mut pin, mut session, mut timestep) = (None, None, None);
+        for field in fields {
+            match (field.get(..1), field.get(1..)) {
+                (Some("P"), Some(hash)) if pin.is_none() => pin = Some(parse_hash(hash)?),
+                (Some("S"), Some(len)) if session.is_none() && len.len() == 3 => {
+                    session = Some(len.parse().map_err(|_| Error::InvalidSuite)?)
+                }
+                (Some("T"), Some(step)) if timestep.is_none() => {
+                    timestep = Some(parse_timestep(step)?)
+                }
+                _ => return Err(Error::InvalidSuite),
+            }
+        }
+
+        Ok(Self {
+            text: suite.to_string(),
+            hash,
+            digits,
+            counter,
+            challenge_format,
+            challenge_len,
+            pin,
+            session,
+            timestep,
+        })
+    }
+}
+
+/// Maps a hash function name used in a suite to its respective enum variant
+fn parse_hash(hash: &str) -> Result<HashFn, Error> {
+    match hash {
+        "SHA1" => Ok(HashFn::SHA1),
+        "SHA256" => Ok(HashFn::SHA256),
+        "SHA512" => Ok(HashFn::SHA512),
+        _ => Err(Error::InvalidSuite),
+    }
+}
+
+/// Parses a timestep of the form [1-59]S, [1-59]M or [1-48]H into a number of seconds
+fn parse_timestep(step: &str) -> Result<u64, Error> {
+    if step.len() < 2 {
+        return Err(Error::InvalidSuite);
+    }
+    let (count, unit) = step.split_at(step.len() - 1);
+    let count: u64 = count.parse().map_err(|_| Error::InvalidSuite)?;
+    let seconds = match unit {
+        "S" if (1..=59).contains(&count) => count,
+        "M" if (1..=59).contains(&count) => count * 60,
+        "H" if (1..=48).contains(&count) => count * 60 * 60,
+        _ => return Err(Error::InvalidSuite),
+    };
+    Ok(seconds)
+}
+
+/// Values used to build the data input for a response
+/// Only the fields required by the suite are used, with the others being ignored
+#[derive(Clone, Debug, Default)]
+pub struct DataInput {
+    pub counter: Option<u64>,
+    pub challenge: String,
+    pub pin: Option<String>, // Plaintext PIN, which is hashed with the suite's PIN hash function
+    pub session: Option<String>, // Session information as hexadecimal
+    pub timestamp: Option<i64>, // UNIX timestamp (in seconds)
+}
+
+/// Calculates the response for a challenge, using a suite and the shared secret key
+pub fn generate(suite: &Suite, key: &[u8], input: &DataInput) -> Result<String, Error> {
+    let message = data_input(suite, input)?;
+    let mac = hmac::generate(key, &message, &suite.hash);
+
+    // If the suite doesn't truncate, output the whole HMAC as hexadecimal
+    if suite.digits == 0 {
+        return Ok(hex::encode(&mac));
+    }
+
+    // Otherwise, truncate in the same way as a HOTP code
+    let d: usize = suite.digits.into();
+    let code = u64::from(truncate(&mac)) % 10_u64.pow(d as u32);
+    Ok(format!("{:0>d$}", code, d = d))
+}
+
+/// Builds the message passed into the HMAC, which is the concatenation of the suite, a 0 byte, and each of the suite's fields in order
+fn data_input(suite: &Suite, input: &DataInput) -> Result<Vec<u8>, Error> {
+    let mut message = suite.text.as_bytes().to_vec();
+    message.push(0);
+
+    // Counter as an 8 byte big endian integer
+    if suite.counter {
+        let counter = input.counter.ok_or(Error::MissingCounter)?;
+        message.extend(counter.to_be_bytes());
+    }
+
+    message.extend(challenge_bytes(suite, &input.challenge)?);
+
+    // PIN is hashed, rather than being included as is
+    if let Some(hash) = suite.pin {
+        let pin = input.pin.as_ref().ok_or(Error::MissingPin)?;
+        message.extend(hash.digest(pin));
+    }
+
+    // Session information is placed at the start of its field, with the rest filled with 0s
+    if let Some(len) = suite.session {
+        let session = input.session.as_deref().unwrap_or_default();
+        let mut session = hex::decode(session).map_err(|_| Error::InvalidSession)?;
+        if session.len() > len {
+            return Err(Error::InvalidSession);
+        }
+        session.resize(len, 0);
+        message.extend(session);
+    }
+
+    // Timestamp as an 8 byte big endian number of timesteps
+    if let Some(step) = suite.timestep {
+        let timestamp = input.timestamp.ok_or(Error::MissingTime)?;
+        let timestep: u64 = (timestamp / step as i64)
+            .try_into()
+            .map_err(|_| Error::MissingTime)?;
+        message.extend(timestep.to_be_bytes());
+    }
+
+    Ok(message)
+}
+
+/// Converts a challenge into its 128 byte representation in the data input
+/// The challenge is converted to hexadecimal, then padded to the right with 0s
+fn challenge_bytes(suite: &Suite, challenge: &str) -> Result<Vec<u8>, Error> {
+    if challenge.is_empty() {
+        return Err(Error::InvalidChallenge);
+    }
+
+    let digits = match suite.challenge_format {
+        ChallengeFormat::Numeric => decimal_to_hex(challenge).ok_or(Error::InvalidChallenge)?,
+        ChallengeFormat::Alphanumeric => {
+            if !challenge.is_ascii() {
+                return Err(Error::InvalidChallenge);
+            }
+            hex::encode(challenge.as_bytes())
+        }
+        ChallengeFormat::Hex => {
+            if !challenge.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(Error::InvalidChallenge);
+            }
+            challenge.to_string()
+        }
+    };
+
+    // Challenge must fit within its field
+    if digits.len() > CHALLENGE_SIZE * 2 {
+        return Err(Error::InvalidChallenge);
+    }
+
+    // Pads to the right, so an odd length hex string will have its final digit in the high half of a byte
+    let padded = format!("{:0<w$}", digits, w = CHALLENGE_SIZE * 2);
+    hex::decode(&padded).map_err(|_| Error::InvalidChallenge)
+}
+
+/// Converts a string of decimal digits of any length into hexadecimal, without leading 0s
+fn decimal_to_hex(decimal: &str) -> Option<String> {
+    // Accumulate the number in base 256, least significant byte first, by multiplying by 10 and adding each digit
+    let mut bytes: Vec<u8> = Vec::new();
+    for c in decimal.chars() {
+        let mut carry = c.to_digit(10)?;
+        for byte in bytes.iter_mut() {
+            let value = u32::from(*byte) * 10 + carry;
+            *byte = (value & 0xFF) as u8;
+            carry = value >> 8;
+        }
+        if carry > 0 {
+            bytes.push(carry as u8);
+        }
+    }
+
+    bytes.reverse();
+    let digits = hex::encode(&bytes);
+    let digits = digits.trim_start_matches('0');
+    Some(if digits.is_empty() {
+        String::from("0")
+    } else {
+        digits.to_string()
+    })
+}
+
+/// Error enum to handle errors with OCRA suites and inputs
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    InvalidSuite,     // Suite string couldn't be parsed
+    InvalidChallenge, // Challenge doesn't match the suite's challenge format
+    InvalidSession,   // Session information isn't valid hex, or is too long
+    MissingCounter,   // Suite requires a counter, but none was given
+    MissingPin,       // Suite requires a PIN, but none was given
+    MissingTime,      // Suite requires a timestamp, but none (or an invalid one) was given
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RFC 6287 Appendix C keys
+    const KEY_20: &[u8] = b"12345678901234567890";
+    const KEY_32: &[u8] = b"12345678901234567890123456789012";
+    const KEY_64: &[u8] = b"1234567890123456789012345678901234567890123456789012345678901234";
+
+    // RFC 6287 Appendix C timestamp, 0x132d0b6 minutes
+    const TIMESTAMP: i64 = 0x132d0b6 * 60;
+
+    fn respond(suite: &str, key: &[u8], input: DataInput) -> String {
+        generate(&suite.parse().unwrap(), key, &input).unwrap()
+    }
+
+    fn challenge(challenge: &str) -> DataInput {
+        DataInput {
+            challenge: String::from(challenge),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn parse_suite() {
+        let suite: Suite = "OCRA-1:HOTP-SHA256-8:C-QN08-PSHA1".parse().unwrap();
+        assert_eq!(suite.hash, HashFn::SHA256);
+        assert_eq!(suite.digits, 8);
+        assert!(suite.counter);
+        assert_eq!(suite.challenge_format, ChallengeFormat::Numeric);
+        assert_eq!(suite.challenge_len, 8);
+        assert_eq!(suite.pin, Some(HashFn::SHA1));
+        assert_eq!(suite.session, None);
+        assert_eq!(suite.timestep, None);
+    }
+
+    #[test]
+    fn parse_suite_session_time() {
+        let suite: Suite = "OCRA-1:HOTP-SHA512-0:QH40-S128-T2H".parse().unwrap();
+        assert_eq!(suite.digits, 0);
+        assert!(!suite.counter);
+        assert_eq!(suite.challenge_format, ChallengeFormat::Hex);
+        assert_eq!(suite.session, Some(128));
+        assert_eq!(suite.timestep, Some(7200));
+    }
+
+    #[test]
+    fn parse_suite_invalid() {
+        for suite in [
+            "OCRA-2:HOTP-SHA1-6:QN08",
+            "OCRA-1:TOTP-SHA1-6:QN08",
+            "OCRA-1:HOTP-MD5-6:QN08",
+            "OCRA-1:HOTP-SHA1-3:QN08",
+            "OCRA-1:HOTP-SHA1-6:C",
+            "OCRA-1:HOTP-SHA1-6:QX08",
+            "OCRA-1:HOTP-SHA1-6:QN65",
+            "OCRA-1:HOTP-SHA1-6:QN08-T60M",
+            "OCRA-1:HOTP-SHA1-6:QN08-C",
+            "OCRA-1:HOTP-SHA1-6:QN08:",
+            "OCRA-1:HOTP-SHA1-6:QN08-",
+        ] {
+            assert_eq!(suite.parse::<Suite>(), Err(Error::InvalidSuite), "{suite}");
+        }
+    }
+
+    #[test]
+    fn decimal_hex() {
+        assert_eq!(decimal_to_hex("00000000").unwrap(), "0");
+        assert_eq!(decimal_to_hex("11111111").unwrap(), "a98ac7");
+        assert_eq!(decimal_to_hex("4095").unwrap(), "fff");
+        assert!(decimal_to_hex("12a").is_none());
+    }
+
+    #[test]
+    fn rfc6287_one_way() {
+        let responses = [
+            "237653", "243178", "653583", "740991", "608993", "388898", "816933", "224598",
+            "750600", "294470",
+        ];
+        for (i, response) in responses.into_iter().enumerate() {
+            let q = i.to_string().repeat(8);
+            assert_eq!(
+                respond("OCRA-1:HOTP-SHA1-6:QN08", KEY_20, challenge(&q)),
+                response
+            );
+        }
+    }
+
+    #[test]
+    fn rfc6287_counter_pin() {
+        let responses = [
+            "65347737", "86775851", "78192410", "71565254", "10104329", "65983500", "70069104",
+            "91771096", "75011558", "08522129",
+        ];
+        for (c, response) in responses.into_iter().enumerate() {
+            let input = DataInput {
+                counter: Some(c as u64),
+                pin: Some(String::from("1234")),
+                ..challenge("12345678")
+            };
+            assert_eq!(
+                respond("OCRA-1:HOTP-SHA256-8:C-QN08-PSHA1", KEY_32, input),
+                response
+            );
+        }
+    }
+
+    #[test]
+    fn rfc6287_pin() {
+        let responses = ["83238735", "01501458", "17957585", "86776967", "86807031"];
+        for (i, response) in responses.into_iter().enumerate() {
+            let input = DataInput {
+                pin: Some(String::from("1234")),
+                ..challenge(&i.to_string().repeat(8))
+            };
+            assert_eq!(
+                respond("OCRA-1:HOTP-SHA256-8:QN08-PSHA1", KEY_32, input),
+                response
+            );
+        }
+    }
+
+    #[test]
+    fn rfc6287_counter() {
+        let responses = [
+            "07016083", "63947962", "70123924", "25341727", "33203315", "34205738", "44343969",
+            "51946085", "20403879", "31409299",
+        ];
+        for (c, response) in responses.into_iter().enumerate() {
+            let input = DataInput {
+                counter: Some(c as u64),
+                ..challenge(&c.to_string().repeat(8))
+            };
+            assert_eq!(
+                respond("OCRA-1:HOTP-SHA512-8:C-QN08", KEY_64, input),
+                response
+            );
+        }
+    }
+
+    #[test]
+    fn rfc6287_time() {
+        let responses = ["95209754", "55907591", "22048402", "24218844", "36209546"];
+        for (i, response) in responses.into_iter().enumerate() {
+            let input = DataInput {
+                timestamp: Some(TIMESTAMP),
+                ..challenge(&i.to_string().repeat(8))
+            };
+            assert_eq!(
+                respond("OCRA-1:HOTP-SHA512-8:QN08-T1M", KEY_64, input),
+                response
+            );
+        }
+    }
+
+    #[test]
+    fn rfc6287_mutual() {
+        let responses = ["28247970", "01984843", "65387857", "03351211", "83412541"];
+        for (i, response) in responses.into_iter().enumerate() {
+            let q = format!("CLI2222{i}SRV1111{i}");
+            assert_eq!(
+                respond("OCRA-1:HOTP-SHA256-8:QA08", KEY_32, challenge(&q)),
+                response
+            );
+        }
+    }
+
+    #[test]
+    fn rfc6287_signature() {
+        let responses = ["77537423", "31970405", "10235557", "95213541", "65360607"];
+        for (i, response) in responses.into_iter().enumerate() {
+            let input = DataInput {
+                timestamp: Some(TIMESTAMP),
+                ..challenge(&format!("SIG1{i}00000"))
+            };
+            assert_eq!(
+                respond("OCRA-1:HOTP-SHA512-8:QA10-T1M", KEY_64, input),
+                response
+            );
+        }
+    }
+
+    #[test]
+    fn session_padded() {
+        // Session information shorter than the field is followed by 0s
+        let input = DataInput {
+            session: Some(String::from("ab")),
+            ..challenge("00000000")
+        };
+        assert_eq!(
+            respond("OCRA-1:HOTP-SHA1-6:QN08-S064", KEY_20, input),
+            "824891"
+        );
+    }
+
+    #[test]
+    fn missing_inputs() {
+        let suite: Suite = "OCRA-1:HOTP-SHA256-8:C-QN08-PSHA1".parse().unwrap();
+        let input = challenge("12345678");
+        assert_eq!(generate(&suite, KEY_32, &input), Err(Error::MissingCounter));
+        let input = DataInput {
+            counter: Some(0),
+            ..input
+        };
+        assert_eq!(generate(&suite, KEY_32, &input), Err(Error::MissingPin));
+    }
+
+    #[test]
+    fn invalid_challenge() {
+        let suite: Suite = "OCRA-1:HOTP-SHA1-6:QN08".parse().unwrap();
+        // Too long to fit in 128 bytes
+        assert_eq!(
+            generate(&suite, KEY_20, &challenge(&"9".repeat(400))),
+            Err(Error::InvalidChallenge)
+        );
+        // Empty
+        assert_eq!(
+            generate(&suite, KEY_20, &challenge("")),
+            Err(Error::InvalidChallenge)
+        );
+        // Not numeric
+        assert_eq!(
+            generate(&suite, KEY_20, &challenge("1234ABCD")),
+            Err(Error::InvalidChallenge)
+        );
+    }
+}
@@ -1,25 +1,43 @@
 // Generates password code from HMAC
 
+use crate::clock::Clock;
 use crate::hmac;
 use crate::key::Key;
-use chrono::Utc;
 
 use serde::{Deserialize, Serialize};
 
-/// An enum to represent the main method to generate codes, either being a time based code (TOTP), or a counter based code (HOTP)
+/// Characters used by Steam Guard codes, in index order
+const STEAM_CHARS: &[u8] = b"23456789BCDFGHJKMNPQRTVWXY";
+/// Length of a Steam Guard code, which is fixed
+pub const STEAM_LENGTH: u8 = 5;
+/// Largest window [verify] checks, far wider than any drift worth accepting, so a huge window can't make it generate billions of codes
+const MAX_WINDOW: i64 = 100;
+
+/// An enum to represent the main method to generate codes, either being a time based code (TOTP), a counter based code (HOTP), or a Steam Guard code
+/// Steam Guard codes are time based, but are output as alphanumeric characters rather than digits
 #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)] // Needed to be converted to json, cloned implicitly & sorted
 pub enum OTPMethod {
     TOTP,
     HOTP(u64), // Stores current count value
+    Steam,
 }
 
 impl OTPMethod {
     // This is a method to allow for modification of the enum within Rust's concurrency checker
-    /// Increments counter within HOTP variant, doing nothing if self is a TOTP variant
+    /// Increments counter within HOTP variant, doing nothing if self is a time based variant
     pub fn increment_counter(&mut self) {
         match self {
             Self::HOTP(ref mut c) => *c += 1,
-            Self::TOTP => (),
+            Self::TOTP | Self::Steam => (),
+        }
+    }
+
+    /// Moves the HOTP counter past a code that was matched by [verify] at the given offset, doing nothing if self is a time based variant
+    /// As per RFC 4226 §7.4, the counter is set to the value after the matched one, so that the matched code cannot be reused
+    pub fn resynchronise(&mut self, offset: u64) {
+        match self {
+            Self::HOTP(ref mut c) => *c = c.saturating_add(offset).saturating_add(1),
+            Self::TOTP | Self::Steam => (),
         }
     }
 
@@ -27,6 +45,7 @@ impl OTPMethod {
         match self {
             Self::HOTP(_) => OTPMethodStripped::HOTP,
             Self::TOTP => OTPMethodStripped::TOTP,
+            Self::Steam => OTPMethodStripped::Steam,
         }
     }
 }
@@ -35,37 +54,22 @@ impl OTPMethod {
 
 pub enum OTPMethodStripped {
     TOTP,
-    HOTP
+    HOTP,
+    Steam,
 }
 
-impl Key {
-    /// Converts key's secret to base 32
-    // Validation done when keys entered, so can be treated as always valid
-    fn to_b32(&self) -> Vec<u8> {
-        // Base 32 character set in index order, so find method will return base 32 representation of a char
-        let base32chars = "ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
-        let upper = self.secret.to_ascii_uppercase();
-
-        // Fold through each character, appending the binary representation of its corresponding base 32 value to a string with each item
-        let b32 = upper.chars().fold(String::new(), |acc, x| {
-            acc + format!("{:05b}", base32chars.find(x).unwrap()).as_str()
-        });
-
-        // Converts into byte array
-        let bytes = b32.into_bytes();
-
-        // Outputs bytes, with any trailing bits after the last full bytes being padded with 0s to the left
-        bytes
-            .chunks(8)
-            .map(|x| {
-                u8::from_str_radix(String::from_utf8(x.to_vec()).unwrap().as_str(), 2).unwrap()
-            })
-            .collect()
+impl OTPMethodStripped {
+    /// Whether codes of this method change over time, rather than on a counter increment
+    pub fn is_timed(&self) -> bool {
+        match self {
+            Self::TOTP | Self::Steam => true,
+            Self::HOTP => false,
+        }
     }
 }
 
 /// Truncate the MAC array with a generated index to 31 bits
-fn truncate(mac: &Vec<u8>) -> u32 {
+pub(crate) fn truncate(mac: &Vec<u8>) -> u32 {
     // Takes the 4 least significant bits of the MAC and use them as a byte offset
     let lsb = mac[mac.len() - 1] & 0b00001111;
     let extracted = extract31(mac, lsb.into());
@@ -82,38 +86,102 @@ fn extract31(mac: &Vec<u8>, i: usize) -> [u8; 4] {
     extract
 }
 
-/// Generate a OTP code from a key
-pub fn generate(key: &Key) -> u32 {
-    // Convert the key to base 32, won't fail as key previously validated
-    let b32key = key.to_b32();
-
-    // If TOTP, count variable used in HMAC is based on current time
+/// Calculates the count variable used in the HMAC for a key at a UNIX timestamp (in seconds)
+fn count_at(key: &Key, timestamp: i64) -> u64 {
+    // If TOTP, count variable used in HMAC is based on the timestamp
     // If HOTP, count variable is stored with key
-
-    let count: u64 = match key.options.method {
-        OTPMethod::TOTP => {
+    match key.options.method {
+        OTPMethod::TOTP | OTPMethod::Steam => {
             // Calculate timestep
-            // Timestep updates by 1 every interval seconds, achieved by rounding current timestamp down to multiple of interval
-            let now = Utc::now();
-            let timestep = now.timestamp() / key.options.interval as i64;
-            timestep.try_into().unwrap()
+            // Timestep updates by 1 every interval seconds, achieved by rounding timestamp down to multiple of interval
+            // Times before the UNIX epoch are treated as the epoch itself
+            let timestep = timestamp / key.options.interval as i64;
+            timestep.try_into().unwrap_or(0)
         }
         OTPMethod::HOTP(c) => c,
-    };
+    }
+}
 
-    // Calculate HMAC value, with the key as the base 32 secret, message as a big endian representation of the count, and the hash function specified by the key
-    let mac = hmac::generate(&b32key[..], &count.to_be_bytes(), &key.options.hash);
+/// Renders a truncated HMAC as a Steam Guard code
+/// Each character is chosen by the remainder when dividing by the size of the character set, least significant first
+fn steam_code(truncated: u32) -> String {
+    let len = STEAM_CHARS.len() as u32;
+    (0..STEAM_LENGTH)
+        .scan(truncated, |remaining, _| {
+            let c = STEAM_CHARS[(*remaining % len) as usize] as char;
+            *remaining /= len;
+            Some(c)
+        })
+        .collect()
+}
 
-    // Truncate the HMAC into 31 bits and then further into a key.options.length length code
-    let totp = truncate(&mac) % 10_u32.pow(key.options.length.into());
+/// Generate a OTP code from a key for a specific count value
+fn generate_from_count(key: &Key, count: u64) -> String {
+    // Calculate HMAC value, with the key as the raw secret, message as a big endian representation of the count, and the hash function specified by the key
+    let mac = hmac::generate(&key.secret, &count.to_be_bytes(), &key.options.hash);
 
-    // Return the code as a u32, which can safely store all generated numbers, as the max code value is 999,999
-    totp
+    // Truncate the HMAC into 31 bits
+    let truncated = truncate(&mac);
+
+    // Steam Guard codes use their own character set, so are rendered separately
+    if let OTPMethod::Steam = key.options.method {
+        return steam_code(truncated);
+    }
+
+    // Further truncate into a key.options.length length code
+    // Calculated as a u64, as 10^10 (the modulus for a 10 digit code) would overflow a u32
+    let d: usize = key.options.length.into();
+    let code = u64::from(truncated) % 10_u64.pow(d as u32);
+
+    // Return the code rendered as a string of length d, padded with leading 0s if necessary
+    format!("{:0>d$}", code, d = d)
+}
+
+/// Generate a OTP code from a key, using a [Clock] to get the current time
+pub fn generate(key: &Key, clock: &dyn Clock) -> String {
+    generate_at(key, clock.now().timestamp())
+}
+
+/// Generate a OTP code from a key as it would be at a UNIX timestamp (in seconds)
+/// The timestamp is ignored by HOTP keys
+pub fn generate_at(key: &Key, timestamp: i64) -> String {
+    generate_from_count(key, count_at(key, timestamp))
+}
+
+/// Checks whether a candidate code is valid for a key, allowing for the key to have drifted by up to window steps
+/// For TOTP keys, the timesteps up to window either side of the current one are accepted, to allow for clock drift and transmission delay
+/// For HOTP keys, the next window counters after the current one are accepted (look-ahead resynchronisation as per RFC 4226 §7.4)
+/// Windows larger than [MAX_WINDOW] are treated as [MAX_WINDOW]
+/// Returns the offset from the current step that the code matched at, or None if no step within the window matched
+/// Candidates are compared case insensitively, as Steam Guard codes are often typed in lowercase
+pub fn verify(key: &Key, candidate: &str, window: u64, clock: &dyn Clock) -> Option<i64> {
+    let count = count_at(key, clock.now().timestamp());
+
+    let window = i64::try_from(window).unwrap_or(MAX_WINDOW).min(MAX_WINDOW);
+    let timed = key.options.method.strip().is_timed();
+
+    // Offsets to check, in order of preference
+    // TOTP checks outwards from the current timestep (0, -1, 1, -2, 2...), so the closest match is reported, whereas HOTP only checks ahead
+    let mut offsets = (0..=window)
+        .flat_map(|i| [-i, i])
+        .skip(1) // 0 is only checked once
+        .filter(|offset| timed || *offset >= 0);
+
+    // Return the first offset whose code matches the candidate, skipping any that would take the count out of range
+    offsets.find(|offset| {
+        count
+            .checked_add_signed(*offset)
+            .is_some_and(|c| generate_from_count(key, c).eq_ignore_ascii_case(candidate))
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::clock::{FixedClock, SystemClock};
+    use crate::encoding::b32;
+    use crate::key::CodeOptions;
+    use hash::HashFn;
 
     #[test]
     fn truncation() {
@@ -142,17 +210,235 @@ mod tests {
         assert_eq!(extract31(&mac, 10), [121, 243, 110, 126]);
     }
 
+    // RFC 4226 Appendix D secret, "12345678901234567890" in base 32
+    const RFC_SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+    // RFC 6238 Appendix B secrets for SHA256 & SHA512, the above repeated to 32 & 64 bytes in base 32
+    const RFC_SECRET_SHA256: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZA";
+    const RFC_SECRET_SHA512: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNA";
+
+    fn rfc_hotp_key(counter: u64) -> Key {
+        let options = CodeOptions::new_or_default(Some(OTPMethod::HOTP(counter)), None, None, None);
+        Key::new(b32(RFC_SECRET), String::new(), String::new(), options)
+    }
+
+    #[test]
+    fn verify_totp_current() {
+        let key = Key::new(
+            b32(RFC_SECRET),
+            String::new(),
+            String::new(),
+            Default::default(),
+        );
+        // Code may roll over between generation and verification, so only check that a nearby step matched
+        assert!(verify(&key, &generate(&key, &SystemClock), 1, &SystemClock).is_some());
+    }
+
+    #[test]
+    fn verify_totp_drift() {
+        let key = Key::new(
+            b32(RFC_SECRET),
+            String::new(),
+            String::new(),
+            Default::default(),
+        );
+        let clock = FixedClock::from_timestamp(1111111109).unwrap();
+        // Code from 2 timesteps earlier
+        let code = generate_at(&key, 1111111109 - 60);
+        assert_eq!(verify(&key, &code, 2, &clock), Some(-2));
+        assert_eq!(verify(&key, &code, 1, &clock), None);
+    }
+
     #[test]
-    fn b32_0() {
-        let key = Key::new(String::from("Primm"), String::new(), Default::default());
-        let expect = vec![0x7c, 0x50, 0xc6, 0x00];
-        assert_eq!(key.to_b32(), expect)
+    fn generate_fixed_clock() {
+        let options = CodeOptions::new_or_default(None, None, Some(8), None);
+        let key = Key::new(b32(RFC_SECRET), String::new(), String::new(), options);
+        let clock = FixedClock::from_timestamp(59).unwrap();
+        // RFC 6238 Appendix B
+        assert_eq!(generate(&key, &clock), "94287082");
+        assert_eq!(generate_at(&key, 59), "94287082");
     }
 
     #[test]
-    fn b32_1() {
-        let key = Key::new(String::from("manonam"), String::new(), Default::default());
-        let expect = vec![0x60, 0x1a, 0xe6, 0x81, 0x04];
-        assert_eq!(key.to_b32(), expect)
+    fn verify_hotp_current() {
+        assert_eq!(verify(&rfc_hotp_key(0), "755224", 0, &SystemClock), Some(0));
+    }
+
+    #[test]
+    fn verify_hotp_look_ahead() {
+        // Code for counter 3
+        assert_eq!(verify(&rfc_hotp_key(0), "969429", 5, &SystemClock), Some(3));
+    }
+
+    #[test]
+    fn verify_hotp_outside_window() {
+        assert_eq!(verify(&rfc_hotp_key(0), "969429", 2, &SystemClock), None);
+    }
+
+    #[test]
+    fn verify_hotp_no_look_behind() {
+        // Code for counter 1, which has already been used
+        assert_eq!(verify(&rfc_hotp_key(2), "287082", 5, &SystemClock), None);
+    }
+
+    #[test]
+    fn verify_large_window() {
+        let key = rfc_hotp_key(0);
+        // Windows too large for an i64 are limited, rather than wrapping to a negative window
+        assert_eq!(verify(&key, "969429", u64::MAX, &SystemClock), Some(3));
+        let mut method = OTPMethod::HOTP(u64::MAX - 1);
+        method.resynchronise(u64::MAX);
+        assert_eq!(method, OTPMethod::HOTP(u64::MAX));
+    }
+
+    #[test]
+    fn resynchronise_hotp() {
+        let mut key = rfc_hotp_key(0);
+        let offset = verify(&key, "969429", 5, &SystemClock).unwrap();
+        key.options.method.resynchronise(offset as u64);
+        assert_eq!(key.options.method, OTPMethod::HOTP(4));
+    }
+
+    /// Checks a key's codes against a table of (time, code) pairs from RFC 6238 Appendix B
+    fn rfc6238(secret: &str, hash: HashFn, table: [(i64, &str); 6]) {
+        let options = CodeOptions::new_or_default(None, Some(hash), Some(8), Some(30));
+        let key = Key::new(b32(secret), String::new(), String::new(), options);
+        for (time, code) in table {
+            let clock = FixedClock::from_timestamp(time).unwrap();
+            assert_eq!(generate(&key, &clock), code, "T = {time}");
+        }
+    }
+
+    #[test]
+    fn rfc6238_sha1() {
+        rfc6238(
+            RFC_SECRET,
+            HashFn::SHA1,
+            [
+                (59, "94287082"),
+                (1111111109, "07081804"),
+                (1111111111, "14050471"),
+                (1234567890, "89005924"),
+                (2000000000, "69279037"),
+                (20000000000, "65353130"),
+            ],
+        );
+    }
+
+    #[test]
+    fn rfc6238_sha256() {
+        rfc6238(
+            RFC_SECRET_SHA256,
+            HashFn::SHA256,
+            [
+                (59, "46119246"),
+                (1111111109, "68084774"),
+                (1111111111, "67062674"),
+                (1234567890, "91819424"),
+                (2000000000, "90698825"),
+                (20000000000, "77737706"),
+            ],
+        );
+    }
+
+    #[test]
+    fn rfc6238_sha512() {
+        rfc6238(
+            RFC_SECRET_SHA512,
+            HashFn::SHA512,
+            [
+                (59, "90693936"),
+                (1111111109, "25091201"),
+                (1111111111, "99943326"),
+                (1234567890, "93441116"),
+                (2000000000, "38618901"),
+                (20000000000, "47863826"),
+            ],
+        );
+    }
+
+    #[test]
+    fn ten_digits() {
+        // Truncated value is 1284755224 for count 0, which is 10 digits long, so is output in full
+        let options = CodeOptions::new_or_default(Some(OTPMethod::HOTP(0)), None, Some(10), None);
+        let key = Key::new(b32(RFC_SECRET), String::new(), String::new(), options);
+        assert_eq!(generate(&key, &SystemClock), "1284755224");
+    }
+
+    #[test]
+    fn nine_digits() {
+        let options = CodeOptions::new_or_default(Some(OTPMethod::HOTP(0)), None, Some(9), None);
+        let key = Key::new(b32(RFC_SECRET), String::new(), String::new(), options);
+        assert_eq!(generate(&key, &SystemClock), "284755224");
+    }
+
+    #[test]
+    fn leading_zero() {
+        // Truncated value is 137359152 for count 2
+        let options = CodeOptions::new_or_default(Some(OTPMethod::HOTP(2)), None, Some(9), None);
+        let key = Key::new(b32(RFC_SECRET), String::new(), String::new(), options);
+        assert_eq!(generate(&key, &SystemClock), "137359152");
+        let options = CodeOptions::new_or_default(Some(OTPMethod::HOTP(2)), None, Some(10), None);
+        let key = Key::new(b32(RFC_SECRET), String::new(), String::new(), options);
+        assert_eq!(generate(&key, &SystemClock), "0137359152");
+    }
+
+    #[test]
+    fn steam() {
+        let key = Key::new(
+            b32(RFC_SECRET),
+            String::new(),
+            String::new(),
+            CodeOptions::steam(),
+        );
+        assert_eq!(generate_at(&key, 59), "PV9M4");
+        assert_eq!(generate_at(&key, 1111111109), "PY4YB");
+        assert_eq!(generate_at(&key, 1234567890), "VHHQY");
+    }
+
+    #[test]
+    fn steam_chars() {
+        // 0 maps to the first character each time, and 25 to the last
+        assert_eq!(steam_code(0), "22222");
+        assert_eq!(steam_code(25), "Y2222");
+        assert_eq!(steam_code(26), "23222");
+    }
+
+    #[test]
+    fn verify_steam_lowercase() {
+        let key = Key::new(
+            b32(RFC_SECRET),
+            String::new(),
+            String::new(),
+            CodeOptions::steam(),
+        );
+        let clock = FixedClock::from_timestamp(59).unwrap();
+        assert_eq!(verify(&key, "pv9m4", 0, &clock), Some(0));
+    }
+
+    #[test]
+    fn rfc4226_hotp() {
+        // RFC 4226 Appendix D, HOTP values for counts 0 to 9
+        let codes = [
+            "755224", "287082", "359152", "969429", "338314", "254676", "287922", "162583",
+            "399871", "520489",
+        ];
+        for (count, code) in codes.into_iter().enumerate() {
+            let key = rfc_hotp_key(count as u64);
+            assert_eq!(generate(&key, &SystemClock), code, "Count = {count}");
+        }
+    }
+
+    #[test]
+    fn rfc4226_truncate() {
+        // RFC 4226 Appendix D, truncated values for counts 0 to 9
+        let values = [
+            1284755224, 1094287082, 137359152, 1726969429, 1640338314, 868254676, 1918287922,
+            82162583, 673399871, 645520489,
+        ];
+        let key = rfc_hotp_key(0);
+        for (count, value) in values.into_iter().enumerate() {
+            let mac = hmac::generate(&key.secret, &(count as u64).to_be_bytes(), &HashFn::SHA1);
+            assert_eq!(truncate(&mac), value, "Count = {count}");
+        }
     }
 }
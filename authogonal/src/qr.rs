@@ -1,150 +1,153 @@
-// Handles QR code parsing, using the URI schema from https://github.com/google/google-authenticator/wiki/Key-Uri-Format
+// Handles QR code reading, with the key URIs they contain being parsed by the uri module, or the migration module for Google Authenticator exports
 
 use std::path::PathBuf;
 
-use crate::key::{CodeOptions, Key};
-use crate::otp::OTPMethod;
-use hash::HashFn;
-use regex::Regex;
+use crate::error;
+use crate::key::Key;
+use crate::migration;
+use crate::uri;
+use image::{DynamicImage, RgbaImage};
 
-/// Reads raw data from QR
-fn read_qr(img_path: PathBuf) -> Result<String, Box<dyn std::error::Error>> {
-    // Open image from entered bath
-    let img = image::open(img_path)?;
+/// Keys parsed from each QR code in an image, in the order they were found
+/// Codes that couldn't be read or parsed are kept as errors, so they can be reported individually
+pub type Codes = Vec<Result<Key, error::Error>>;
 
+/// Reads raw data from every QR code in a decoded image
+/// Fails only if no QR codes can be found at all
+fn decode(img: &DynamicImage) -> Result<Vec<Result<String, Error>>, Error> {
     // Use default bardecoder decoder to decode image
     let decoder = bardecoder::default_decoder();
-    let results = decoder.decode(&img);
-    // Return the 1st decoded QR code, or if one cannot be found return a read error 
-    Ok(results.into_iter().nth(0).ok_or(Error::Read)??)
+    let results: Vec<_> = decoder
+        .decode(img)
+        .into_iter()
+        .map(|r| r.map_err(|_| Error::Read))
+        .collect();
+    if results.is_empty() {
+        return Err(Error::Read);
+    }
+    Ok(results)
 }
 
-/// Parses the main required structure of the URI scheme using RegEx
-pub fn parse(img_path: PathBuf) -> Result<Key, Box<dyn std::error::Error>> {
-    // Read in the uri text
-    let uri = read_qr(img_path)?;
+/// Reads every QR code in a decoded image and parses the URIs they contain
+fn parse_image(img: &DynamicImage) -> Result<Codes, error::Error> {
+    Ok(parse_codes(read_image(img)?))
+}
 
-    // Regex to match to text read from QR
-    let re = Regex::new(r"^otpauth://(?<type>(?:h|t)otp)/(?<label>.+)\?(?<params>.*)$").unwrap();
+/// Reads the text of every QR code in a decoded image, without parsing it
+fn read_image(img: &DynamicImage) -> Result<Vec<Result<String, error::Error>>, error::Error> {
+    Ok(decode(img)?
+        .into_iter()
+        .map(|text| text.map_err(error::Error::from))
+        .collect())
+}
 
-    // Match the URI string to the regex, saving the data that falls within capturing groups (i.e. within brackets)
-    let caps = re.captures(&uri).ok_or(Error::Read)?;
+/// Reads every QR code in several image files, such as a screenshot of each batch of an export, and parses the URIs they contain
+/// Codes from every file are parsed together, so an export split across the files is only reported as missing batches if the files don't hold all of them
+pub fn parse_files(img_paths: Vec<PathBuf>) -> Codes {
+    combine(
+        img_paths
+            .into_iter()
+            .map(|path| {
+                let img = image::open(path).map_err(|_| Error::Read)?;
+                read_image(&img)
+            })
+            .collect(),
+    )
+}
 
-    // Parse any optional paramaters
-    let params = parse_params(caps["params"].to_owned())?;
+/// Parses the texts read from several images at once, keeping an image that couldn't be read as an error
+fn combine(reads: Vec<Result<Vec<Result<String, error::Error>>, error::Error>>) -> Codes {
+    let mut texts = Vec::new();
+    for read in reads {
+        match read {
+            Ok(read) => texts.extend(read),
+            Err(e) => texts.push(Err(e)),
+        }
+    }
+    parse_codes(texts)
+}
 
-    // Match the method string to respective enum
-    let method = match &caps["type"] {
-        "totp" => OTPMethod::TOTP,
-        "hotp" => OTPMethod::HOTP(params.counter.unwrap_or(0)),
-        _ => panic!(),
-    };
+/// Parses the text read from each QR code, which can also be entered by the user, into keys
+/// Google Authenticator exports hold several keys per code, and any codes missing from an export are reported as errors
+pub fn parse_codes(texts: Vec<Result<String, error::Error>>) -> Codes {
+    let mut codes = Vec::new();
+    let mut batches = Vec::new();
+    for text in texts {
+        match text {
+            Err(e) => codes.push(Err(e)),
+            Ok(text) if migration::is_migration(&text) => match migration::parse(&text) {
+                Ok(batch) => batches.push(batch),
+                Err(e) => codes.push(Err(e.into())),
+            },
+            Ok(text) => codes.push(uri::parse(&text).map_err(error::Error::from)),
+        }
+    }
 
-    let name = caps["label"].to_string();
+    // Keys from exports are listed in the order they were exported, followed by the batches that weren't read
+    let missing = migration::missing(&batches);
+    batches.sort_by_key(|b| (b.id, b.index));
+    for batch in batches {
+        codes.extend(
+            batch
+                .keys
+                .into_iter()
+                .map(|k| k.map_err(error::Error::from)),
+        );
+    }
+    for (index, size) in missing {
+        codes.push(Err(migration::Error::MissingBatch(index, size).into()));
+    }
+    codes
+}
 
-    // Return a new key built from the QR data, with all non-present parameters being initialised to default
-    Ok(Key::new(
-        params.secret,
-        name,
-        CodeOptions::new_or_default(Some(method), params.algorithm, params.digits, params.period),
-    ))
+/// Reads a QR code from an image and parses the URI it contains
+/// If the image has several QR codes, the first that can be parsed is used
+pub fn parse(img_path: PathBuf) -> Result<Key, error::Error> {
+    first(parse_all(img_path)?)
 }
 
-/// Struct to store the data that can be found in the PARAMETERS section of the URI schema
-/// Allows for easier passing between functions
-/// Optional data is stored within an option, with secret being the only required parameter
-#[derive(Debug)]
-struct Params {
-    secret: String,
-    algorithm: Option<HashFn>,
-    digits: Option<u8>,
-    counter: Option<u64>,
-    period: Option<u32>,
+/// Picks the first code that could be parsed, or the first error if none could
+fn first(codes: Codes) -> Result<Key, error::Error> {
+    let mut codes = codes.into_iter();
+    // Always has at least one code, otherwise reading the image would have failed
+    let first = codes.next().ok_or(Error::Read)?;
+    if first.is_ok() {
+        return first;
+    }
+    codes.find(Result::is_ok).unwrap_or(first)
 }
 
-/// Parses String data from the parameters field, using RegEx
-/// The parameters can be in any order, and are mostly optional
-fn parse_params(params: String) -> Result<Params, Box<dyn std::error::Error>> {
-    // RegEx to match an individual parameter, all checked separately as the parameters are unordered
-    let secret_re = Regex::new(r"(?:^|\?|&)secret=([^&?]+)(?:&|$)").unwrap();
-    let algorithm_re = Regex::new(r"(?:^|\?|&)algorithm=(SHA(?:1|256|512))(?:&|$)").unwrap();
-    let digits_re = Regex::new(r"(?:^|\?|&)digits=(\d+)(?:&|$)").unwrap();
-    let counter_re = Regex::new(r"(?:^|\?|&)counter=(\d+)(?:&|$)").unwrap();
-    let period_re = Regex::new(r"(?:^|\?|&)period=(\d+)(?:&|$)").unwrap();
-
-    // 1 is used as the index for the 1st capturing group, as 0 returns the whole string
-
-    // Match the secret in the string, if not present, throw error as is a required field
-    let secret = secret_re.captures(&params).ok_or(Error::NoSecret)?[1].to_owned();
-
-    // Map the algorithm string (if present) to its respective enum varient
-    let algorithm = if let Some(algorithm) = algorithm_re.captures(&params) {
-        let string = algorithm.get(1).ok_or(Error::InvalidParamater)?.as_str();
-        match string {
-            "SHA1" => Some(HashFn::SHA1),
-            "SHA256" => Some(HashFn::SHA256),
-            "SHA512" => Some(HashFn::SHA512),
-            _ => return Err(Box::new(Error::InvalidParamater)),
-        }
-    } else {
-        None
-    };
-
-    // If digits value present, parse to u8
-    let digits = if let Some(digits) = digits_re.captures(&params) {
-        Some(
-            digits
-                .get(1)
-                .ok_or(Error::InvalidParamater)?
-                .as_str()
-                .parse()?,
-        )
-    } else {
-        None
-    };
-
-    // If counter value present, parse to u64
-    let counter = if let Some(counter) = counter_re.captures(&params) {
-        Some(
-            counter
-                .get(1)
-                .ok_or(Error::InvalidParamater)?
-                .as_str()
-                .parse()?,
-        )
-    } else {
-        None
-    };
-
-    // If period value present, parse to u32
-    let period = if let Some(period) = period_re.captures(&params) {
-        Some(
-            period
-                .get(1)
-                .ok_or(Error::InvalidParamater)?
-                .as_str()
-                .parse()?,
-        )
-    } else {
-        None
-    };
-
-    // Return parsed parameters
-    Ok(Params {
-        secret,
-        algorithm,
-        digits,
-        counter,
-        period,
-    })
+/// Reads every QR code in an image file, such as a sheet of exported codes, and parses the URIs they contain
+pub fn parse_all(img_path: PathBuf) -> Result<Codes, error::Error> {
+    // Open image from entered bath
+    let img = image::open(img_path).map_err(|_| Error::Read)?;
+    parse_image(&img)
+}
+
+/// Reads every QR code in an image on the clipboard, such as a screenshot, and parses the URIs they contain
+pub fn parse_clipboard() -> Result<Codes, error::Error> {
+    // Clipboard is opened each time, as it's rarely used
+    let mut clipboard = arboard::Clipboard::new().map_err(|_| Error::Clipboard)?;
+    let img = clipboard.get_image().map_err(|e| match e {
+        arboard::Error::ContentNotAvailable => Error::NoImage,
+        _ => Error::Clipboard,
+    })?;
+    parse_rgba(img.width as u32, img.height as u32, img.bytes.into_owned())
+}
+
+/// Reads every QR code in raw RGBA pixels and parses the URIs they contain
+fn parse_rgba(width: u32, height: u32, bytes: Vec<u8>) -> Result<Codes, error::Error> {
+    // Fails if the number of bytes doesn't match the size
+    let img = RgbaImage::from_raw(width, height, bytes).ok_or(Error::Read)?;
+    parse_image(&DynamicImage::ImageRgba8(img))
 }
 
 /// Error enum to handle errors with QR parsing
 #[derive(Debug)]
 pub enum Error {
-    NoSecret, // No secret was found in QR
-    InvalidParamater, // Another paramater is invalid
-    Read, // QR could not be read from image
+    Read,      // QR could not be read from image
+    Clipboard, // Clipboard couldn't be accessed
+    NoImage,   // Clipboard doesn't contain an image
 }
 
 impl std::error::Error for Error {}
@@ -158,6 +161,10 @@ impl std::fmt::Display for Error {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::encoding::b32;
+    use crate::key::CodeOptions;
+    use crate::otp::OTPMethod;
+    use hash::HashFn;
 
     #[test]
     fn google() {
@@ -165,8 +172,9 @@ mod tests {
         assert_eq!(
             key.unwrap(),
             Key::new(
-                String::from("JBSWY3DPEHPK3PXP"),
-                String::from("Example:alice@google.com"),
+                b32("JBSWY3DPEHPK3PXP"),
+                String::from("Example"),
+                String::from("alice@google.com"),
                 Default::default()
             )
         )
@@ -184,8 +192,9 @@ mod tests {
         assert_eq!(
             key.unwrap(),
             Key::new(
-                String::from("JBSWY3DPEHPK3PXP"),
-                String::from("Example:alice@google.com"),
+                b32("JBSWY3DPEHPK3PXP"),
+                String::from("Example"),
+                String::from("alice@google.com"),
                 options
             )
         )
@@ -202,10 +211,148 @@ mod tests {
         );
         assert_eq!(
             key.unwrap(),
-            Key::new(String::from("manonam"), String::from("Primm"), options)
+            Key::new(
+                b32("manonam"),
+                String::new(),
+                String::from("Primm"),
+                options
+            )
         )
     }
 
+    #[test]
+    fn rgba() {
+        let img = image::open("src/test_data/qr/google.png")
+            .unwrap()
+            .to_rgba8();
+        let (width, height) = img.dimensions();
+        let codes = parse_rgba(width, height, img.into_raw()).unwrap();
+        assert_eq!(codes.len(), 1);
+        assert_eq!(
+            codes[0].as_ref().unwrap().label(),
+            "Example:alice@google.com"
+        );
+
+        // Size doesn't match the pixels
+        assert!(parse_rgba(width + 1, height, vec![0; 4]).is_err());
+    }
+
+    #[test]
+    fn multiple() {
+        // Two codes side by side, as on a sheet of exported codes
+        let google = image::open("src/test_data/qr/google.png")
+            .unwrap()
+            .to_rgba8();
+        let totp = image::open("src/test_data/qr/totp.png").unwrap().to_rgba8();
+        let (width, height) = (
+            google.width() + totp.width(),
+            google.height().max(totp.height()),
+        );
+        let mut sheet = RgbaImage::from_pixel(width, height, image::Rgba([255; 4]));
+        image::imageops::overlay(&mut sheet, &google, 0, 0);
+        image::imageops::overlay(&mut sheet, &totp, google.width() as i64, 0);
+
+        let mut labels: Vec<_> = parse_image(&DynamicImage::ImageRgba8(sheet))
+            .unwrap()
+            .into_iter()
+            .map(|key| key.unwrap().label())
+            .collect();
+        labels.sort();
+        assert_eq!(labels, ["Example:alice@google.com", "Primm"]);
+    }
+
+    #[test]
+    fn multiple_errors() {
+        // Codes that can't be parsed are kept alongside the others
+        let google = image::open("src/test_data/qr/google.png")
+            .unwrap()
+            .to_rgba8();
+        let err = image::open("src/test_data/qr/err.png").unwrap().to_rgba8();
+        let mut sheet = RgbaImage::from_pixel(
+            google.width() + err.width(),
+            google.height().max(err.height()),
+            image::Rgba([255; 4]),
+        );
+        image::imageops::overlay(&mut sheet, &google, 0, 0);
+        image::imageops::overlay(&mut sheet, &err, google.width() as i64, 0);
+
+        let codes = parse_image(&DynamicImage::ImageRgba8(sheet)).unwrap();
+        assert_eq!(codes.len(), 2);
+        assert_eq!(codes.iter().filter(|c| c.is_ok()).count(), 1);
+    }
+
+    #[test]
+    fn codes() {
+        // First of 2 batches of an export, without any keys
+        let export = String::from("otpauth-migration://offline?data=GAIgAA%3D%3D");
+        let codes = parse_codes(vec![
+            Ok(export),
+            Ok(String::from("otpauth://totp/a?secret=JBSWY3DP")),
+            Err(Error::Read.into()),
+        ]);
+        assert_eq!(codes.len(), 3);
+        assert_eq!(codes[0].as_ref().unwrap().account, "a");
+        assert!(codes[1].is_err());
+        assert!(matches!(
+            codes[2],
+            Err(error::Error::Parse(error::ParseError::Migration(
+                migration::Error::MissingBatch(1, 2)
+            )))
+        ));
+    }
+
+    #[test]
+    fn combined_export() {
+        // Each batch of a 3 batch export is read from a separate image
+        let export = |data: &str| Ok(vec![Ok(format!("otpauth-migration://offline?data={data}"))]);
+        let codes = combine(vec![
+            export("CgoKBUhlbGxvEgFhGAMgACgH"),
+            export("CgoKBUhlbGxvEgFiGAMgASgH"),
+            Err(Error::Read.into()),
+            export("CgoKBUhlbGxvEgFjGAMgAigH"),
+        ]);
+        assert_eq!(codes.len(), 4);
+        assert!(codes[0].is_err());
+        let accounts: Vec<_> = codes[1..]
+            .iter()
+            .map(|key| key.as_ref().unwrap().account.as_str())
+            .collect();
+        assert_eq!(accounts, ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn first_code() {
+        let key = |account: &str| {
+            Key::new(
+                b32("JBSWY3DP"),
+                String::new(),
+                String::from(account),
+                Default::default(),
+            )
+        };
+        // The first code is used when every code can be parsed
+        let codes = vec![Ok(key("a")), Ok(key("b"))];
+        assert_eq!(first(codes).unwrap().account, "a");
+        // Codes that can't be parsed are skipped
+        let codes = vec![Err(Error::Read.into()), Ok(key("b"))];
+        assert_eq!(first(codes).unwrap().account, "b");
+        assert!(first(vec![Err(Error::Read.into())]).is_err());
+    }
+
+    #[test]
+    fn oversized_export() {
+        // Export claiming to be split into 2^32 - 1 batches, which is rejected rather than reporting each as missing
+        let export = String::from("otpauth-migration://offline?data=GP%2F%2F%2F%2F8P");
+        let codes = parse_codes(vec![Ok(export)]);
+        assert_eq!(codes.len(), 1);
+        assert!(matches!(
+            codes[0],
+            Err(error::Error::Parse(error::ParseError::Migration(
+                migration::Error::Payload
+            )))
+        ));
+    }
+
     // Has an empty secret
     #[test]
     fn invalid() {
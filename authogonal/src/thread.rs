@@ -1,12 +1,12 @@
 // Handles creation of backend threads that handle an individual key
 
-use chrono::Utc;
-
 use std::sync::mpsc;
 use std::sync::mpsc::{Receiver, Sender};
+use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
 
+use crate::clock::Clock;
 use crate::key::Key;
 use crate::otp::{generate, OTPMethod};
 use crate::ui::main::{OTPMessageIn, OTPMessageOut};
@@ -15,10 +15,11 @@ use eframe::egui;
 
 const TICK_SPEED: Duration = Duration::from_millis(1000);
 
-/// Calculates duration between current time and the next timestep increment
-fn time_to_timestep(interval: u32) -> Duration {
+/// Calculates duration between the clock's current time and the next timestep increment
+fn time_to_timestep(interval: u32, clock: &dyn Clock) -> Duration {
     // Gets current UNIX (millisecond) time
-    let now_stamp: u64 = Utc::now().timestamp_millis().try_into().unwrap();
+    // Times before the UNIX epoch are treated as the epoch itself
+    let now_stamp: u64 = clock.now().timestamp_millis().try_into().unwrap_or(0);
     // Gets UNIX (millisecond) time of next timestap
     let interval: u64 = <u32 as Into<u64>>::into(interval) * 1000;
     let next_timestep_stamp = ((now_stamp / interval) + 1) * interval;
@@ -30,10 +31,11 @@ fn time_to_timestep(interval: u32) -> Duration {
 
 /// Spawns a thread that handles the code generation for a single key
 /// Returns a Sender of [OTPMessageIn] to send messages to the thread and a Receiver [OTPMessageOut] to receive code messages from the thread
-/// The EGUI context is used within the thread to signal a screen refresh
+/// The EGUI context is used within the thread to signal a screen refresh, and the [Clock] is shared with the thread to generate time based codes
 pub fn spawn_thread(
     ctx: &egui::Context,
     key: &Key,
+    clock: Arc<dyn Clock>,
 ) -> (Receiver<OTPMessageOut>, Sender<OTPMessageIn>) {
     // Channel for sending codes out
     let (tx_out, rx_out) = mpsc::channel::<OTPMessageOut>();
@@ -45,21 +47,21 @@ pub fn spawn_thread(
     let ctx = ctx.clone(); // CTX designed to be cheap to clone
 
     // Generates initial code and sends it to the GUI
-    let code = generate(&key_clone);
+    let code = generate(&key_clone, &*clock);
     tx_out.send(OTPMessageOut::Code(code)).unwrap();
 
     // Generates initial progress and sends it to the GUI
-    let wait = time_to_timestep(key_clone.options.interval);
+    let wait = time_to_timestep(key_clone.options.interval, &*clock);
     let progress = 1. - (wait.as_secs_f32() / key_clone.options.interval as f32);
     tx_out.send(OTPMessageOut::Tick(progress)).unwrap();
 
     // Determine type of key, as TOTP and HOTP codes need to be handled by different logic
     match key.options.method {
-        OTPMethod::TOTP => {
+        OTPMethod::TOTP | OTPMethod::Steam => {
             // Thread for time based keys
             thread::spawn(move || loop {
                 // Wait until next tick or code needs to be updated
-                let dur_to_code = time_to_timestep(key_clone.options.interval);
+                let dur_to_code = time_to_timestep(key_clone.options.interval, &*clock);
 
                 // Handles when application first opened and ticks aren't in sync with code
                 let dur_to_tick =
@@ -79,7 +81,7 @@ pub fn spawn_thread(
                 }
 
                 // Calculate percentage of time remaining
-                let time = time_to_timestep(key_clone.options.interval);
+                let time = time_to_timestep(key_clone.options.interval, &*clock);
                 let progress = 1. - (time.as_secs_f32() / key_clone.options.interval as f32);
 
                 if let Err(_) = tx_out.send(OTPMessageOut::Tick(progress)) {
@@ -88,7 +90,7 @@ pub fn spawn_thread(
 
                 if update_code {
                     // Generate code from key, now that the timestep has updated
-                    let code = generate(&key_clone);
+                    let code = generate(&key_clone, &*clock);
 
                     if let Err(_) = tx_out.send(OTPMessageOut::Code(code)) {
                         continue;
@@ -106,9 +108,16 @@ pub fn spawn_thread(
                     match r {
                         OTPMessageIn::Increment(e_key) => {
                             // On increment message, increment counter, calculate code & send to GUI
-                            key_clone.increment(&e_key);
-                            let code = generate(&key_clone);
-                            if let Ok(_) = tx_out.send(OTPMessageOut::Code(code)) {
+                            // If the counter couldn't be saved, the code is left unchanged so it stays in sync with the file, and the error is shown instead
+                            let message = match key_clone.increment(&e_key) {
+                                Ok(()) => OTPMessageOut::Code(generate(&key_clone, &*clock)),
+                                Err(e) => OTPMessageOut::Error(format!(
+                                    "Couldn't increment {}: {}",
+                                    key_clone.label(),
+                                    e
+                                )),
+                            };
+                            if let Ok(_) = tx_out.send(message) {
                                 ctx.request_repaint();
                             }
                         }
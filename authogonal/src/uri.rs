@@ -0,0 +1,389 @@
+// Handles key URI parsing, using the schema from https://github.com/google/google-authenticator/wiki/Key-Uri-Format
+// Steam Guard keys are also supported, either as a totp URI with the encoder=steam parameter, or as a steam://SECRET URI
+// Used for URIs read from QR codes as well as ones entered as text
+
+use crate::encoding::SecretEncoding;
+use crate::key::{CodeOptions, Key, LENGTH_RANGE};
+use crate::otp::OTPMethod;
+use hash::HashFn;
+
+/// Parses a key URI into a [Key]
+pub fn parse(uri: &str) -> Result<Key, Error> {
+    // Surrounding whitespace is often copied along with the URI
+    let uri = uri.trim();
+    let (scheme, rest) = uri.split_once("://").ok_or(Error::Scheme)?;
+
+    // Steam URIs only contain the secret, so all other data is fixed
+    if scheme.eq_ignore_ascii_case("steam") {
+        return Ok(Key::new(
+            decode_secret(&decode(rest)?)?,
+            String::from("Steam"),
+            String::new(),
+            CodeOptions::steam(),
+        ));
+    }
+    if !scheme.eq_ignore_ascii_case("otpauth") {
+        return Err(Error::Scheme);
+    }
+
+    // Fragments aren't part of the schema, so are ignored
+    let rest = rest.split_once('#').map_or(rest, |(rest, _)| rest);
+    // Secret is required, so there must always be parameters
+    let (path, query) = rest.split_once('?').ok_or(Error::NoSecret)?;
+    let (kind, label) = path.split_once('/').unwrap_or((path, ""));
+
+    let params = parse_params(query)?;
+    let (issuer, account) = parse_label(&decode(label)?, params.issuer);
+    let secret = decode_secret(params.secret.as_deref().ok_or(Error::NoSecret)?)?;
+    let kind = kind.to_ascii_lowercase();
+
+    // Steam keys are TOTP keys with a different encoder, and have fixed options
+    if let Some(encoder) = params.encoder {
+        return match (kind.as_str(), encoder.to_ascii_lowercase().as_str()) {
+            ("totp", "steam") => Ok(Key::new(secret, issuer, account, CodeOptions::steam())),
+            _ => Err(Error::InvalidParameter(String::from("encoder"))),
+        };
+    }
+
+    // Match the method string to respective enum
+    let method = match kind.as_str() {
+        "totp" => OTPMethod::TOTP,
+        "hotp" => OTPMethod::HOTP(params.counter.unwrap_or(0)),
+        _ => return Err(Error::Type(kind)),
+    };
+
+    // Return a new key built from the URI, with all non-present parameters being initialised to default
+    Ok(Key::new(
+        secret,
+        issuer,
+        account,
+        CodeOptions::new_or_default(Some(method), params.algorithm, params.digits, params.period),
+    ))
+}
+
+/// Splits the label into an issuer & account name, where the issuer is an optional prefix separated by a colon
+/// If the issuer parameter is given it's used instead of the prefix, as the spec recommends, including when the two don't match
+pub(crate) fn parse_label(label: &str, issuer: Option<String>) -> (String, String) {
+    // Spaces are allowed around the colon
+    let (prefix, account) = match label.split_once(':') {
+        Some((prefix, account)) => (prefix.trim(), account.trim()),
+        None => ("", label.trim()),
+    };
+    let issuer = issuer.unwrap_or_else(|| prefix.to_string());
+    (issuer, account.to_string())
+}
+
+/// Decodes a secret from a URI, which is always base 32
+fn decode_secret(secret: &str) -> Result<Vec<u8>, Error> {
+    SecretEncoding::Base32
+        .decode(secret)
+        .map_err(|_| Error::InvalidSecret)
+}
+
+/// Struct to store the data that can be found in the PARAMETERS section of the URI schema
+/// Allows for easier passing between functions
+/// Every parameter is stored within an option, as missing ones either have defaults or are reported by the caller
+#[derive(Debug, Default)]
+struct Params {
+    secret: Option<String>,
+    issuer: Option<String>,
+    algorithm: Option<HashFn>,
+    digits: Option<u8>,
+    counter: Option<u64>,
+    period: Option<u32>,
+    encoder: Option<String>,
+}
+
+/// Parses the query string of a URI
+/// Parameters can be in any order, with names matched ignoring case, and unknown parameters are skipped
+fn parse_params(query: &str) -> Result<Params, Error> {
+    let mut params = Params::default();
+
+    for pair in query.split('&').filter(|p| !p.is_empty()) {
+        // A parameter without a value is treated as having an empty one
+        let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let name = decode(name)?.to_ascii_lowercase();
+        // Spaces are sometimes encoded as + in query strings, which can't appear in any known value
+        let value = decode(&value.replace('+', " "))?;
+
+        let invalid = || Error::InvalidParameter(name.clone());
+        match name.as_str() {
+            "secret" => set(&mut params.secret, &name, value)?,
+            "issuer" => set(&mut params.issuer, &name, value.trim().to_string())?,
+            "encoder" => set(&mut params.encoder, &name, value)?,
+            // Map the algorithm string to its respective enum varient
+            "algorithm" => {
+                let algorithm = match value.to_ascii_uppercase().as_str() {
+                    "SHA1" => HashFn::SHA1,
+                    "SHA256" => HashFn::SHA256,
+                    "SHA512" => HashFn::SHA512,
+                    _ => return Err(invalid()),
+                };
+                set(&mut params.algorithm, &name, algorithm)?
+            }
+            // Digits are rejected if they're a length that can't be generated
+            "digits" => {
+                let digits = value.parse().map_err(|_| invalid())?;
+                if !LENGTH_RANGE.contains(&digits) {
+                    return Err(invalid());
+                }
+                set(&mut params.digits, &name, digits)?
+            }
+            "counter" => set(
+                &mut params.counter,
+                &name,
+                value.parse().map_err(|_| invalid())?,
+            )?,
+            // Period is rejected if it's 0, as a timestep can't be calculated
+            "period" => match value.parse() {
+                Ok(0) | Err(_) => return Err(invalid()),
+                Ok(period) => set(&mut params.period, &name, period)?,
+            },
+            _ => (),
+        }
+    }
+
+    Ok(params)
+}
+
+/// Stores the value of a parameter, rejecting parameters that appear more than once
+fn set<T>(param: &mut Option<T>, name: &str, value: T) -> Result<(), Error> {
+    if param.is_some() {
+        return Err(Error::Duplicate(name.to_string()));
+    }
+    *param = Some(value);
+    Ok(())
+}
+
+/// Decodes percent-encoded text, where %XX is the byte with hex value XX
+/// The decoded bytes must be valid UTF-8
+pub(crate) fn decode(text: &str) -> Result<String, Error> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut iter = text.bytes();
+    while let Some(b) = iter.next() {
+        if b != b'%' {
+            bytes.push(b);
+            continue;
+        }
+        // Both hex digits must follow the %
+        let mut digit = || {
+            iter.next()
+                .and_then(|d| (d as char).to_digit(16))
+                .ok_or(Error::Encoding)
+        };
+        let (high, low) = (digit()?, digit()?);
+        bytes.push((high * 16 + low) as u8);
+    }
+    String::from_utf8(bytes).map_err(|_| Error::Encoding)
+}
+
+/// Error enum to handle errors with URI parsing
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    Scheme,                   // URI doesn't start with otpauth:// or steam://
+    Type(String),             // Key type isn't totp or hotp
+    Encoding,                 // Percent-encoding is invalid, or doesn't decode to UTF-8
+    NoSecret,                 // No secret was found in URI
+    InvalidSecret,            // The secret isn't valid base 32
+    Duplicate(String),        // A parameter is given more than once
+    InvalidParameter(String), // A parameter's value is invalid
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Scheme => write!(f, "Not an otpauth:// or steam:// URI"),
+            Self::Type(kind) => write!(f, "Unknown key type {}", kind),
+            Self::Encoding => write!(f, "Invalid percent-encoding in URI"),
+            Self::NoSecret => write!(f, "URI has no secret"),
+            Self::InvalidSecret => write!(f, "Invalid secret in URI"),
+            Self::Duplicate(name) => write!(f, "URI has more than one {} parameter", name),
+            Self::InvalidParameter(name) => write!(f, "Invalid {} parameter in URI", name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::b32;
+
+    #[test]
+    fn google() {
+        let key =
+            parse("otpauth://totp/Example:alice@google.com?secret=JBSWY3DPEHPK3PXP&issuer=Example");
+        assert_eq!(
+            key.unwrap(),
+            Key::new(
+                b32("JBSWY3DPEHPK3PXP"),
+                String::from("Example"),
+                String::from("alice@google.com"),
+                Default::default()
+            )
+        )
+    }
+
+    #[test]
+    fn percent_encoded() {
+        let key = parse("otpauth://totp/ACME%20Co%3Ajohn.doe%40email.com?secret=JBSWY3DPEHPK3PXP&issuer=ACME%20Co").unwrap();
+        assert_eq!(key.issuer, "ACME Co");
+        assert_eq!(key.account, "john.doe@email.com");
+
+        // Spaces in parameters may also be encoded as +
+        let key = parse("otpauth://totp/john?secret=JBSWY3DPEHPK3PXP&issuer=ACME+Co").unwrap();
+        assert_eq!(key.issuer, "ACME Co");
+    }
+
+    #[test]
+    fn invalid_encoding() {
+        assert_eq!(decode("a%2").unwrap_err(), Error::Encoding);
+        assert_eq!(decode("%zz").unwrap_err(), Error::Encoding);
+        // Doesn't decode to UTF-8
+        assert_eq!(decode("%ff").unwrap_err(), Error::Encoding);
+        assert_eq!(decode("%E2%82%AC").unwrap(), "€");
+    }
+
+    #[test]
+    fn case_insensitive() {
+        let key =
+            parse("OTPAUTH://HOTP/a?SECRET=jbswy3dpehpk3pxp&Algorithm=sha256&Counter=20").unwrap();
+        let options = CodeOptions::new_or_default(
+            Some(OTPMethod::HOTP(20)),
+            Some(HashFn::SHA256),
+            None,
+            None,
+        );
+        assert_eq!(key.secret, b32("JBSWY3DPEHPK3PXP"));
+        assert_eq!(key.options, options);
+    }
+
+    #[test]
+    fn totp_params() {
+        let key = parse("otpauth://totp/Primm?period=15&digits=4&algorithm=SHA512&secret=manonam")
+            .unwrap();
+        let options = CodeOptions::new_or_default(
+            Some(OTPMethod::TOTP),
+            Some(HashFn::SHA512),
+            Some(4),
+            Some(15),
+        );
+        assert_eq!(key.options, options);
+    }
+
+    #[test]
+    fn unknown_params() {
+        // Parameters used by other apps are skipped
+        let key =
+            parse("otpauth://totp/a?secret=JBSWY3DPEHPK3PXP&image=https%3A%2F%2Fexample.com&lock&")
+                .unwrap();
+        assert_eq!(key.account, "a");
+    }
+
+    #[test]
+    fn duplicate_params() {
+        let err = parse("otpauth://totp/a?secret=JBSWY3DPEHPK3PXP&SECRET=JBSWY3DPEHPK3PXP");
+        assert_eq!(err.unwrap_err(), Error::Duplicate(String::from("secret")));
+    }
+
+    #[test]
+    fn params_digits() {
+        let params = parse_params("secret=JBSWY3DPEHPK3PXP&digits=10").unwrap();
+        assert_eq!(params.digits, Some(10));
+    }
+
+    #[test]
+    fn params_out_of_range() {
+        let invalid = |name: &str| Err(Error::InvalidParameter(String::from(name)));
+        assert_eq!(parse_params("digits=11").map(|_| ()), invalid("digits"));
+        assert_eq!(parse_params("digits=3").map(|_| ()), invalid("digits"));
+        assert_eq!(parse_params("digits=256").map(|_| ()), invalid("digits"));
+        assert_eq!(parse_params("period=0").map(|_| ()), invalid("period"));
+        assert_eq!(
+            parse_params("algorithm=MD5").map(|_| ()),
+            invalid("algorithm")
+        );
+        assert_eq!(parse_params("counter=-1").map(|_| ()), invalid("counter"));
+    }
+
+    #[test]
+    fn issuer() {
+        let key =
+            parse("otpauth://totp/ACME:%20john@example.com?secret=JBSWY3DPEHPK3PXP&issuer=ACME")
+                .unwrap();
+        assert_eq!(key.issuer, "ACME");
+        assert_eq!(key.account, "john@example.com");
+
+        // Parameter alone, without a prefix
+        let key = parse("otpauth://totp/john?secret=JBSWY3DPEHPK3PXP&issuer=ACME").unwrap();
+        assert_eq!(key.label(), "ACME:john");
+
+        // Neither is required
+        let key = parse("otpauth://totp/john?secret=JBSWY3DPEHPK3PXP").unwrap();
+        assert_eq!(key.issuer, "");
+        assert_eq!(key.account, "john");
+    }
+
+    #[test]
+    fn issuer_mismatch() {
+        // Parameter is preferred when it doesn't match the prefix
+        let key = parse("otpauth://totp/Old:john?issuer=New&secret=JBSWY3DPEHPK3PXP").unwrap();
+        assert_eq!(key.issuer, "New");
+        assert_eq!(key.account, "john");
+    }
+
+    #[test]
+    fn steam_encoder() {
+        let key = parse("otpauth://totp/Steam:primm?secret=JBSWY3DPEHPK3PXP&encoder=steam");
+        assert_eq!(
+            key.unwrap(),
+            Key::new(
+                b32("JBSWY3DPEHPK3PXP"),
+                String::from("Steam"),
+                String::from("primm"),
+                CodeOptions::steam()
+            )
+        )
+    }
+
+    #[test]
+    fn steam_scheme() {
+        let key = parse("steam://JBSWY3DPEHPK3PXP");
+        assert_eq!(
+            key.unwrap(),
+            Key::new(
+                b32("JBSWY3DPEHPK3PXP"),
+                String::from("Steam"),
+                String::new(),
+                CodeOptions::steam()
+            )
+        )
+    }
+
+    #[test]
+    fn unknown_encoder() {
+        assert!(parse("otpauth://totp/a?secret=JBSWY3DPEHPK3PXP&encoder=other").is_err());
+        assert!(parse("otpauth://hotp/a?secret=JBSWY3DPEHPK3PXP&encoder=steam").is_err());
+    }
+
+    #[test]
+    fn invalid() {
+        assert_eq!(parse("https://example.com").unwrap_err(), Error::Scheme);
+        assert_eq!(parse("otpauth").unwrap_err(), Error::Scheme);
+        assert_eq!(
+            parse("otpauth://motp/a?secret=JBSWY3DPEHPK3PXP").unwrap_err(),
+            Error::Type(String::from("motp"))
+        );
+        assert_eq!(parse("otpauth://totp/a").unwrap_err(), Error::NoSecret);
+        assert_eq!(
+            parse("otpauth://totp/a?issuer=b").unwrap_err(),
+            Error::NoSecret
+        );
+        assert_eq!(
+            parse("otpauth://totp/a?secret=JBSW1Y3DP").unwrap_err(),
+            Error::InvalidSecret
+        );
+        assert_eq!(parse("steam://2082").unwrap_err(), Error::InvalidSecret);
+    }
+}
@@ -1,90 +1,526 @@
 // Crate that provides functions to save and load encrypted data with AES256GCM encryption
+// Keys are derived from passwords with Argon2id, and each file starts with a versioned header describing how to derive the key & decrypt the data
 
 use aes_gcm::{
-    aead::{Aead, AeadCore, KeyInit, OsRng},
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng, Payload},
     Aes256Gcm, Key,
 };
+use argon2::Argon2;
 
-use hash::Hashable;
 use std::path::Path;
 use std::{
     fs::File,
     io::{Read, Write},
 };
 
+/// Bytes at the start of every file with a header, so they can be told apart from legacy files, which start with a random nonce
+const MAGIC: &[u8; 8] = b"AUTHOGNL";
+/// Current version of the file format, always used when saving
+/// Version 1 headers hold only the salt & parameters, version 2 headers are fully self-describing and bound to the data as associated data
+pub const VERSION: u8 = 2;
+/// Identifier for Argon2id in the header's KDF field
+const KDF_ARGON2ID: u8 = 1;
+/// Identifier for AES256GCM in the header's cipher field
+const CIPHER_AES256GCM: u8 = 1;
+/// Length of the salt used when deriving keys, in bytes
+pub const SALT_LEN: usize = 16;
+/// Length of the AES256GCM nonce, in bytes
+const NONCE_LEN: usize = 12;
+/// Length of the AES256GCM authentication tag, in bytes
+const TAG_LEN: usize = 16;
+/// Upper limit on the memory parameter (256 MiB), so a crafted file can't make loading use all available memory
+const MAX_MEMORY: u32 = 1 << 18;
+/// Upper limit on the number of passes, so a crafted file can't make loading hang before the file is checked
+const MAX_ITERATIONS: u32 = 64;
+/// Upper limit on the number of lanes, far above the single lane used by default
+const MAX_PARALLELISM: u32 = 16;
+
 // Password hash stored separately and passed into both functions, to prevent the password being kept in memory
 /// Save a message at the specified path, encrypted using an EncryptionKey
+/// The key's salt & parameters are written in a header, so the key can be derived again from the password
+/// The file is replaced atomically, so it's never left partially written
 pub fn save(
     path: &Path,
     key: &EncryptionKey,
     message: String,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    let data = encrypt(key, message.as_bytes())?;
+    write_atomic(path, &data)?;
+    Ok(())
+}
+
+/// Load a message from the specified path, decrypting using an [EncryptionKey]
+/// If a file doesn't exist, an empty message is returned
+pub fn load(path: &Path, key: &EncryptionKey) -> Result<String, Box<dyn std::error::Error>> {
+    let mut data = Vec::new();
+    match File::open(path) {
+        Ok(mut f) => f.read_to_end(&mut data)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(String::new()),
+        Err(e) => return Err(Box::new(e)),
+    };
+
+    let plaintext = decrypt(key, &data)?;
+    Ok(String::from_utf8(plaintext)?)
+}
+
+/// Re-encrypts the file at a path with a new key, after decrypting it with the old key
+/// The old file is kept intact if anything fails
+pub fn rekey(
+    path: &Path,
+    old_key: &EncryptionKey,
+    new_key: &EncryptionKey,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let message = load(path, old_key)?;
+    let data = encrypt(new_key, message.as_bytes())?;
+
+    // Make sure the new data can be decrypted before replacing the old file
+    if decrypt(new_key, &data)? != message.as_bytes() {
+        return Err(Box::new(Error::WriteError));
+    }
+    write_atomic(path, &data)?;
+
+    Ok(())
+}
+
+/// Writes data to a temporary file beside the path, flushes it to disk, then renames it over the path
+/// A crash or full disk at any point leaves either the old file or the new one, never a mix of both
+fn write_atomic(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    let temp = Path::new(&temp);
+
+    let result = File::create(temp).and_then(|mut file| {
+        file.write_all(data)?;
+        file.sync_all()
+    });
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(temp);
+        return Err(e);
+    }
+
+    // Rename replaces the old file in a single step
+    std::fs::rename(temp, path)?;
+
+    // Flush the directory too, so the rename itself survives a crash
+    // Directories can't be opened as files on all platforms, so this is best effort
+    #[cfg(unix)]
+    if let Some(dir) = path.parent() {
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        let _ = File::open(dir).and_then(|d| d.sync_all());
+    }
+
+    Ok(())
+}
+
+/// Whether the file at a path was saved in an older format, so should be saved again to upgrade it
+pub fn needs_upgrade(path: &Path) -> Result<bool, Box<dyn std::error::Error>> {
+    let mut data = Vec::new();
+    File::open(path)?.read_to_end(&mut data)?;
+    Ok(match parse_header(&data)? {
+        Some(header) => header.version < VERSION,
+        None => true,
+    })
+}
+
+/// Encrypts data with a key, returning the full contents of a file
+/// Legacy keys have no salt or parameters, so produce headerless data
+fn encrypt(key: &EncryptionKey, plaintext: &[u8]) -> Result<Vec<u8>, Error> {
     // Generates cipher from EncryptionKey
-    let key = Key::<Aes256Gcm>::from_slice(key);
-    let cipher = Aes256Gcm::new(&key);
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key.key));
     // 96-bit one time number, unique per message, safely stored plain next to encryption
     let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+
+    // The header is passed to AES256GCM as associated data, so any change to it is detected on decryption
+    let header = match &key.kdf {
+        Kdf::Argon2id { salt, params } => header(salt, params, plaintext.len() + TAG_LEN),
+        Kdf::Legacy => Vec::new(),
+    };
+
     // Encrypts using AES256GCM
-    let ciphertext = match cipher.encrypt(&nonce, message.as_bytes().as_ref()) {
-        Ok(v) => v,
-        Err(_) => return Err(Box::new(Error::WriteError)),
+    let payload = Payload {
+        msg: plaintext,
+        aad: &header,
     };
+    let ciphertext = cipher
+        .encrypt(&nonce, payload)
+        .map_err(|_| Error::WriteError)?;
 
-    // Writes nonce to file then cipher
-    let mut file = File::create(path)?;
-    file.write_all(&nonce)?;
-    file.write_all(&ciphertext)?;
+    let mut data = header;
+    data.extend_from_slice(&nonce);
+    data.extend_from_slice(&ciphertext);
+    Ok(data)
+}
 
-    Ok(())
+/// Decrypts the full contents of a file with a key, dispatching on the file's format version
+fn decrypt(key: &EncryptionKey, data: &[u8]) -> Result<Vec<u8>, Error> {
+    let (kdf, aad, body) = match parse_header(data)? {
+        Some(header) => (header.kdf, header.aad, header.body),
+        // Legacy files are just a nonce & cipher, so must be at least long enough to hold the nonce & tag
+        None if data.len() < NONCE_LEN + TAG_LEN => return Err(Error::FormatError),
+        None => (Kdf::Legacy, &[][..], data),
+    };
+
+    // The key can only decrypt the file if it was derived with the same salt & parameters as those in the header
+    if kdf != key.kdf {
+        return Err(Error::ReadError);
+    }
+
+    open(key, aad, body)
 }
 
-/// Load a message from the specified path, decrypting using an [EncryptionKey]
-/// If a file doesn't exist, a new one will be created and an empty message returned
-pub fn load(path: &Path, key: &EncryptionKey) -> Result<String, Box<dyn std::error::Error>> {
-    let key = Key::<Aes256Gcm>::from_slice(key);
-    let cipher = Aes256Gcm::new(&key);
-
-    if let Ok(mut f) = File::open(path) {
-        // Read exactly 12 bytes to get the nonce
-        let mut nonce = [0; 12];
-        f.read_exact(&mut nonce)?;
-
-        // Read the rest for the cipher
-        let mut ciphertext = Vec::new();
-        f.read_to_end(&mut ciphertext)?;
-
-        // Decrypt and return
-        // Validation done by crate
-        let plaintext = match cipher.decrypt(&(nonce).into(), ciphertext.as_ref()) {
-            Ok(v) => v,
-            Err(_) => return Err(Box::new(Error::ReadError)),
+/// Decrypts a nonce & cipher with a key, checking it was bound to the associated data
+fn open(key: &EncryptionKey, aad: &[u8], body: &[u8]) -> Result<Vec<u8>, Error> {
+    // Split into the nonce and the cipher
+    if body.len() < NONCE_LEN + TAG_LEN {
+        return Err(Error::FormatError);
+    }
+    let (nonce, ciphertext) = body.split_at(NONCE_LEN);
+
+    // Decrypt and return
+    // Validation (including of the associated data) done by crate
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key.key));
+    let payload = Payload {
+        msg: ciphertext,
+        aad,
+    };
+    cipher
+        .decrypt(nonce.into(), payload)
+        .map_err(|_| Error::ReadError)
+}
+
+/// Creates a version 2 header, to be written at the start of a file
+/// Layout: magic, version, KDF id, 3 u32 KDF parameters, salt length, salt, cipher id, u64 cipher length
+fn header(salt: &[u8; SALT_LEN], params: &KdfParams, cipher_len: usize) -> Vec<u8> {
+    let mut header = Vec::new();
+    header.extend_from_slice(MAGIC);
+    header.push(VERSION);
+    header.push(KDF_ARGON2ID);
+    header.extend_from_slice(&params.memory.to_le_bytes());
+    header.extend_from_slice(&params.iterations.to_le_bytes());
+    header.extend_from_slice(&params.parallelism.to_le_bytes());
+    header.push(SALT_LEN as u8);
+    header.extend_from_slice(salt);
+    header.push(CIPHER_AES256GCM);
+    header.extend_from_slice(&(cipher_len as u64).to_le_bytes());
+    header
+}
+
+/// The parsed header of a file
+struct Header<'a> {
+    version: u8,
+    kdf: Kdf,
+    aad: &'a [u8],  // Associated data the cipher was bound to
+    body: &'a [u8], // Nonce & cipher following the header
+}
+
+/// Reads the header from the start of a file's data
+/// Returns None if the data has no header, meaning it is a legacy file
+fn parse_header(data: &[u8]) -> Result<Option<Header<'_>>, Error> {
+    if !data.starts_with(MAGIC) {
+        return Ok(None);
+    }
+
+    let mut reader = Reader {
+        data,
+        pos: MAGIC.len(),
+    };
+    let version = reader.u8()?;
+    let header = match version {
+        1 => parse_v1(&mut reader)?,
+        2 => parse_v2(&mut reader)?,
+        // Saved by a newer version of the app
+        v if v > VERSION => return Err(Error::VersionError),
+        _ => return Err(Error::FormatError),
+    };
+    Ok(Some(header))
+}
+
+/// Version 1 header: salt then 3 u32 parameters, with no associated data
+fn parse_v1<'a>(reader: &mut Reader<'a>) -> Result<Header<'a>, Error> {
+    let salt = reader.bytes(SALT_LEN)?;
+    let params = reader.params()?;
+
+    Ok(Header {
+        version: 1,
+        kdf: Kdf::Argon2id {
+            salt: salt.try_into().map_err(|_| Error::FormatError)?,
+            params,
+        },
+        aad: &[],
+        body: reader.rest(),
+    })
+}
+
+/// Version 2 header, see [header] for its layout
+fn parse_v2<'a>(reader: &mut Reader<'a>) -> Result<Header<'a>, Error> {
+    if reader.u8()? != KDF_ARGON2ID {
+        return Err(Error::FormatError);
+    }
+    let params = reader.params()?;
+    let salt_len = reader.u8()? as usize;
+    let salt = reader.bytes(salt_len)?;
+    if reader.u8()? != CIPHER_AES256GCM {
+        return Err(Error::FormatError);
+    }
+    let cipher_len = reader.u64()?;
+
+    // Everything read so far is the associated data
+    let aad = &reader.data[..reader.pos];
+    let body = reader.rest();
+    // The stored length allows a truncated file to be told apart from an incorrect password
+    if body.len() as u64 != NONCE_LEN as u64 + cipher_len {
+        return Err(Error::FormatError);
+    }
+
+    Ok(Header {
+        version: 2,
+        kdf: Kdf::Argon2id {
+            salt: salt.try_into().map_err(|_| Error::FormatError)?,
+            params,
+        },
+        aad,
+        body,
+    })
+}
+
+/// Reads values in order from a header, failing if the data runs out
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    /// Reads the next n bytes
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], Error> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + n)
+            .ok_or(Error::FormatError)?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    /// Reads a little endian u32
+    fn u32(&mut self) -> Result<u32, Error> {
+        let bytes = self.bytes(4)?.try_into().map_err(|_| Error::FormatError)?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    /// Reads a little endian u64
+    fn u64(&mut self) -> Result<u64, Error> {
+        let bytes = self.bytes(8)?.try_into().map_err(|_| Error::FormatError)?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    /// Reads Argon2id parameters, stored as memory, iterations then parallelism
+    fn params(&mut self) -> Result<KdfParams, Error> {
+        Ok(KdfParams {
+            memory: self.u32()?,
+            iterations: self.u32()?,
+            parallelism: self.u32()?,
+        })
+    }
+
+    /// All data after the values read so far
+    fn rest(&self) -> &'a [u8] {
+        &self.data[self.pos..]
+    }
+}
+
+/// Tunable cost parameters for Argon2id
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KdfParams {
+    pub memory: u32,      // Memory used, in KiB
+    pub iterations: u32,  // Number of passes over the memory
+    pub parallelism: u32, // Number of lanes
+}
+
+impl KdfParams {
+    /// Minimal cost parameters, so tests that need a key (including those in other crates) run quickly
+    /// Far too weak to protect real data
+    pub const TEST: Self = Self {
+        memory: 64,
+        iterations: 1,
+        parallelism: 1,
+    };
+}
+
+impl Default for KdfParams {
+    // OWASP's recommended minimum for Argon2id
+    fn default() -> Self {
+        Self {
+            memory: 19 * 1024,
+            iterations: 2,
+            parallelism: 1,
+        }
+    }
+}
+
+/// How an [EncryptionKey] was derived from a password
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kdf {
+    Legacy, // Single unsalted SHA256 hash, only used to read files saved before keys were salted
+    Argon2id {
+        salt: [u8; SALT_LEN],
+        params: KdfParams,
+    },
+}
+
+/// A key used to encrypt and decrypt files, along with how it was derived so the derivation can be stored with the file
+#[derive(Clone)]
+pub struct EncryptionKey {
+    key: [u8; 32],
+    kdf: Kdf,
+}
+
+impl EncryptionKey {
+    /// Derives a new [EncryptionKey] from a password with a random salt & the default parameters
+    /// Used when creating a file or changing its password
+    pub fn new(password: &str) -> Result<Self, Error> {
+        Self::with_params(password, KdfParams::default())
+    }
+
+    /// Derives a new [EncryptionKey] from a password with a random salt & the given parameters
+    pub fn with_params(password: &str, params: KdfParams) -> Result<Self, Error> {
+        let mut salt = [0; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        Self::derive(password, Kdf::Argon2id { salt, params })
+    }
+
+    /// Derives the [EncryptionKey] for an existing file from a password, using the salt & parameters in the file's header
+    /// Files without a header were saved before keys were salted, so a legacy key is returned
+    pub fn from_file(password: &str, path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut data = Vec::new();
+        File::open(path)?.read_to_end(&mut data)?;
+
+        let kdf = match parse_header(&data)? {
+            Some(header) => header.kdf,
+            None => Kdf::Legacy,
+        };
+        Ok(Self::derive(password, kdf)?)
+    }
+
+    /// Derives an [EncryptionKey] from a password using a given [Kdf]
+    fn derive(password: &str, kdf: Kdf) -> Result<Self, Error> {
+        let key = match &kdf {
+            // Get byte array from slice using the `TryInto` trait
+            // Unwrap will always succeed as SHA256 has constant output size
+            Kdf::Legacy => hash::HashFn::SHA256
+                .digest(&password.to_string())
+                .try_into()
+                .unwrap(),
+            Kdf::Argon2id { salt, params } => {
+                if params.memory > MAX_MEMORY
+                    || params.iterations > MAX_ITERATIONS
+                    || params.parallelism > MAX_PARALLELISM
+                {
+                    return Err(Error::KdfError);
+                }
+                let params = argon2::Params::new(
+                    params.memory,
+                    params.iterations,
+                    params.parallelism,
+                    Some(32),
+                )
+                .map_err(|_| Error::KdfError)?;
+                let argon2 =
+                    Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params);
+
+                let mut key = [0; 32];
+                argon2
+                    .hash_password_into(password.as_bytes(), salt, &mut key)
+                    .map_err(|_| Error::KdfError)?;
+                key
+            }
         };
+        Ok(Self { key, kdf })
+    }
 
-        Ok(String::from_utf8(plaintext)?)
-    } else {
-        File::create(path)?;
-        Ok(String::new())
+    /// How the key was derived
+    pub fn kdf(&self) -> &Kdf {
+        &self.kdf
+    }
+
+    /// Whether the key was derived with the legacy unsalted hash, meaning its file should be migrated to a new key
+    pub fn is_legacy(&self) -> bool {
+        self.kdf == Kdf::Legacy
+    }
+}
+
+// The key itself is left out, so it isn't accidentally logged
+impl std::fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EncryptionKey")
+            .field("kdf", &self.kdf)
+            .finish_non_exhaustive()
     }
 }
 
-// Type alias for improved readability
-pub type EncryptionKey = [u8; 32];
+/// Functions for reading data encrypted by other apps, which store raw keys & nonces rather than using this crate's file format
+pub mod external {
+    use super::*;
+
+    /// Upper limit on the memory scrypt uses (1 GiB), which is 128 * n * r bytes
+    const MAX_SCRYPT_MEMORY: u64 = 1 << 30;
+    /// Upper limit on scrypt's parallelism, as the time taken grows with it
+    const MAX_SCRYPT_PARALLELISM: u32 = 16;
+    /// Upper limit on r * p, far above the values apps use (Aegis uses r = 8, p = 1)
+    const MAX_SCRYPT_BLOCKS: u64 = 128;
+
+    /// Derives a 256 bit key from a password with scrypt
+    /// n is the cost parameter, which must be a power of 2
+    pub fn scrypt(password: &[u8], salt: &[u8], n: u64, r: u32, p: u32) -> Result<[u8; 32], Error> {
+        if !n.is_power_of_two()
+            || n.saturating_mul(r as u64).saturating_mul(128) > MAX_SCRYPT_MEMORY
+            || p > MAX_SCRYPT_PARALLELISM
+            || (r as u64) * (p as u64) > MAX_SCRYPT_BLOCKS
+        {
+            return Err(Error::KdfError);
+        }
+        let params =
+            scrypt::Params::new(n.trailing_zeros() as u8, r, p, 32).map_err(|_| Error::KdfError)?;
+
+        let mut key = [0; 32];
+        scrypt::scrypt(password, salt, &params, &mut key).map_err(|_| Error::KdfError)?;
+        Ok(key)
+    }
+
+    /// Decrypts AES256GCM data with a raw 256 bit key, where the tag is stored separately to the cipher
+    pub fn decrypt(
+        key: &[u8],
+        nonce: &[u8],
+        ciphertext: &[u8],
+        tag: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        if key.len() != 32 || nonce.len() != NONCE_LEN || tag.len() != TAG_LEN {
+            return Err(Error::FormatError);
+        }
+        // The crate expects the tag at the end of the cipher
+        let body = [ciphertext, tag].concat();
 
-/// Generates an [EncryptionKey] from a [Hashable] type using a SHA256 hash algorithm, to be passed into either [load] or [save]
-pub fn password_to_key(password: &impl Hashable) -> EncryptionKey {
-    // Get byte array from slice using the `TryInto` trait
-    // Unwrap will always succeed as SHA256 has constant output size
-    hash::HashFn::SHA256
-        .digest(password)
-        .try_into()
-        .unwrap()
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+        cipher
+            .decrypt(nonce.into(), body.as_slice())
+            .map_err(|_| Error::ReadError)
+    }
 }
 
 // Error type when needing to return Err
 #[derive(Debug)]
 pub enum Error {
-    ReadError,  // Signifies incorrect password
-    WriteError, // Signifies issue with encryption
+    ReadError,    // Signifies incorrect password
+    WriteError,   // Signifies issue with encryption
+    FormatError,  // Signifies file is truncated, or isn't a file saved by this crate
+    VersionError, // Signifies file was saved by a newer version of this crate
+    KdfError,     // Signifies key derivation parameters are invalid
 }
 
 // Implement error so can be returned with other Error types
@@ -100,11 +536,15 @@ impl std::fmt::Display for Error {
 mod tests {
     use super::*;
 
+    fn test_key(password: &str) -> EncryptionKey {
+        EncryptionKey::with_params(password, KdfParams::TEST).unwrap()
+    }
+
     #[test]
     fn integrity() {
         let path = Path::new("test_integrity");
         let plaintext = String::from("manonam");
-        let password = password_to_key(&String::from("2082"));
+        let password = test_key("2082");
         save(path, &password, plaintext.clone()).unwrap();
         assert_eq!(load(path, &password).unwrap(), plaintext);
         let _ = std::fs::remove_file(path);
@@ -114,14 +554,253 @@ mod tests {
     fn empty() {
         let path = Path::new("test_empty");
         let plaintext = String::new();
-        save(
-            path,
-            &password_to_key(&String::from("a")),
-            plaintext.clone(),
-        )
-        .unwrap();
-        let load = load(path, &password_to_key(&String::from("b")));
+        save(path, &test_key("a"), plaintext.clone()).unwrap();
+        let load = load(path, &test_key("b"));
         let _ = std::fs::remove_file(path);
         assert!(load.is_err());
     }
+
+    #[test]
+    fn from_file() {
+        let path = Path::new("test_from_file");
+        let plaintext = String::from("primm");
+        let original = test_key("2082");
+        save(path, &original, plaintext.clone()).unwrap();
+
+        // Key derived again from the header decrypts, but a different password doesn't
+        let key = EncryptionKey::from_file("2082", path).unwrap();
+        let wrong = EncryptionKey::from_file("2083", path).unwrap();
+        let loaded = load(path, &key);
+        let failed = load(path, &wrong);
+        let _ = std::fs::remove_file(path);
+
+        assert_eq!(key.kdf(), original.kdf());
+        assert_eq!(loaded.unwrap(), plaintext);
+        assert!(failed.is_err());
+    }
+
+    #[test]
+    fn legacy() {
+        let path = Path::new("test_legacy");
+        let plaintext = String::from("showroom");
+        let legacy = EncryptionKey::derive("2082", Kdf::Legacy).unwrap();
+        save(path, &legacy, plaintext.clone()).unwrap();
+
+        // Headerless files are detected, and a legacy key derived for them
+        let key = EncryptionKey::from_file("2082", path).unwrap();
+        let loaded = load(path, &key);
+        // A salted key can't decrypt a legacy file
+        let salted = load(path, &test_key("2082"));
+        let _ = std::fs::remove_file(path);
+
+        assert!(key.is_legacy());
+        assert_eq!(loaded.unwrap(), plaintext);
+        assert!(salted.is_err());
+    }
+
+    #[test]
+    fn rekey_file() {
+        let path = Path::new("test_rekey");
+        let plaintext = String::from("primm");
+        let old_key = test_key("2082");
+        let new_key = test_key("1284");
+        save(path, &old_key, plaintext.clone()).unwrap();
+
+        // A key that can't decrypt the file leaves it untouched
+        let failed = rekey(path, &new_key, &old_key);
+        let unchanged = load(path, &old_key);
+        rekey(path, &old_key, &new_key).unwrap();
+        let old = load(path, &old_key);
+        let new = load(path, &new_key);
+        let temp = Path::new("test_rekey.tmp").exists();
+        let _ = std::fs::remove_file(path);
+
+        assert!(failed.is_err());
+        assert_eq!(unchanged.unwrap(), plaintext);
+        assert!(old.is_err());
+        assert_eq!(new.unwrap(), plaintext);
+        assert!(!temp);
+    }
+
+    #[test]
+    fn atomic() {
+        let path = Path::new("test_atomic");
+        let key = test_key("2082");
+        save(path, &key, String::from("manonam")).unwrap();
+        save(path, &key, String::from("primm")).unwrap();
+
+        let loaded = load(path, &key);
+        let temp = Path::new("test_atomic.tmp").exists();
+        let _ = std::fs::remove_file(path);
+
+        assert_eq!(loaded.unwrap(), "primm");
+        assert!(!temp);
+    }
+
+    #[test]
+    fn missing() {
+        let path = Path::new("test_missing");
+        assert_eq!(load(path, &test_key("2082")).unwrap(), "");
+        // Loading doesn't create the file
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn unique_salt() {
+        assert_ne!(test_key("a").kdf(), test_key("a").kdf());
+    }
+
+    /// Encrypts data in the version 1 format, which had no associated data
+    fn encrypt_v1(key: &EncryptionKey, plaintext: &[u8]) -> Vec<u8> {
+        let Kdf::Argon2id { salt, params } = key.kdf else {
+            panic!("Version 1 files always have a salted key")
+        };
+        let mut data = MAGIC.to_vec();
+        data.push(1);
+        data.extend_from_slice(&salt);
+        data.extend_from_slice(&params.memory.to_le_bytes());
+        data.extend_from_slice(&params.iterations.to_le_bytes());
+        data.extend_from_slice(&params.parallelism.to_le_bytes());
+
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key.key));
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        data.extend_from_slice(&nonce);
+        data.extend_from_slice(&cipher.encrypt(&nonce, plaintext).unwrap());
+        data
+    }
+
+    #[test]
+    fn header_round_trip() {
+        let key = test_key("2082");
+        let data = encrypt(&key, b"body").unwrap();
+        let header = parse_header(&data).unwrap().unwrap();
+        assert_eq!(header.version, VERSION);
+        assert_eq!(&header.kdf, key.kdf());
+        assert_eq!(header.body.len(), NONCE_LEN + 4 + TAG_LEN);
+        assert_eq!(decrypt(&key, &data).unwrap(), b"body");
+    }
+
+    #[test]
+    fn version_1() {
+        let path = Path::new("test_version_1");
+        let key = test_key("2082");
+        std::fs::write(path, encrypt_v1(&key, b"primm")).unwrap();
+
+        let derived = EncryptionKey::from_file("2082", path).unwrap();
+        let loaded = load(path, &derived);
+        let upgrade = needs_upgrade(path).unwrap();
+        // Saving again writes the current version
+        save(path, &derived, String::from("primm")).unwrap();
+        let upgraded = needs_upgrade(path).unwrap();
+        let reloaded = load(path, &derived);
+        let _ = std::fs::remove_file(path);
+
+        assert_eq!(loaded.unwrap(), "primm");
+        assert!(upgrade);
+        assert!(!upgraded);
+        assert_eq!(reloaded.unwrap(), "primm");
+    }
+
+    #[test]
+    fn header_tampered() {
+        let key = test_key("2082");
+        let data = encrypt(&key, b"body").unwrap();
+        let header = parse_header(&data).unwrap().unwrap();
+        assert_eq!(open(&key, header.aad, header.body).unwrap(), b"body");
+
+        // Any change to the header the data was bound to is detected, even with the right key
+        let mut aad = header.aad.to_vec();
+        *aad.last_mut().unwrap() ^= 1;
+        assert!(matches!(
+            open(&key, &aad, header.body),
+            Err(Error::ReadError)
+        ));
+
+        // Changing the salt means the key no longer matches
+        let mut data = data.clone();
+        data[MAGIC.len() + 1 + 1 + 12 + 1] ^= 1;
+        assert!(matches!(decrypt(&key, &data), Err(Error::ReadError)));
+    }
+
+    #[test]
+    fn header_invalid() {
+        let key = test_key("2082");
+        // Unknown future version
+        let mut data = encrypt(&key, b"body").unwrap();
+        data[MAGIC.len()] = VERSION + 1;
+        assert!(matches!(parse_header(&data), Err(Error::VersionError)));
+        // Unknown cipher
+        let mut data = encrypt(&key, b"body").unwrap();
+        data[MAGIC.len() + 1 + 1 + 12 + 1 + SALT_LEN] = 0;
+        assert!(matches!(parse_header(&data), Err(Error::FormatError)));
+        // Truncated header
+        assert!(matches!(parse_header(&MAGIC[..]), Err(Error::FormatError)));
+        // Truncated cipher
+        let mut data = encrypt(&key, b"body").unwrap();
+        data.pop();
+        assert!(matches!(decrypt(&key, &data), Err(Error::FormatError)));
+        // Too short to be a legacy file
+        assert!(matches!(decrypt(&key, b"short"), Err(Error::FormatError)));
+    }
+
+    #[test]
+    fn params_too_large() {
+        let params = KdfParams {
+            memory: MAX_MEMORY + 1,
+            ..KdfParams::TEST
+        };
+        assert!(EncryptionKey::with_params("a", params).is_err());
+        let params = KdfParams {
+            iterations: MAX_ITERATIONS + 1,
+            ..KdfParams::TEST
+        };
+        assert!(matches!(
+            EncryptionKey::with_params("a", params),
+            Err(Error::KdfError)
+        ));
+        let params = KdfParams {
+            parallelism: MAX_PARALLELISM + 1,
+            ..KdfParams::TEST
+        };
+        assert!(matches!(
+            EncryptionKey::with_params("a", params),
+            Err(Error::KdfError)
+        ));
+    }
+
+    #[test]
+    fn external_scrypt() {
+        // RFC 7914 test vector, truncated to 32 bytes
+        let key = external::scrypt(b"password", b"NaCl", 1024, 8, 16).unwrap();
+        assert_eq!(key[..4], [0xfd, 0xba, 0xbe, 0x1c],);
+        assert_eq!(key[28..], [0x4b, 0x37, 0x31, 0x62],);
+        assert!(external::scrypt(b"password", b"NaCl", 1000, 8, 16).is_err());
+        assert!(external::scrypt(b"password", b"NaCl", 1 << 30, 8, 1).is_err());
+        // Oversized parameters are rejected rather than overflowing or running for a long time
+        assert!(external::scrypt(b"password", b"NaCl", 1 << 63, 1, 1).is_err());
+        assert!(external::scrypt(b"password", b"NaCl", 1 << 57, 4, 1).is_err());
+        assert!(external::scrypt(b"password", b"NaCl", 16, u32::MAX, 1).is_err());
+        assert!(external::scrypt(b"password", b"NaCl", 16, 1, u32::MAX).is_err());
+        assert!(external::scrypt(b"password", b"NaCl", 16, 64, 16).is_err());
+    }
+
+    #[test]
+    fn external_decrypt() {
+        let key = [7; 32];
+        let nonce = [1; NONCE_LEN];
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+        let sealed = cipher.encrypt((&nonce).into(), &b"message"[..]).unwrap();
+        let (ciphertext, tag) = sealed.split_at(sealed.len() - TAG_LEN);
+
+        let message = external::decrypt(&key, &nonce, ciphertext, tag).unwrap();
+        assert_eq!(message, b"message");
+        assert!(matches!(
+            external::decrypt(&[8; 32], &nonce, ciphertext, tag),
+            Err(Error::ReadError)
+        ));
+        assert!(matches!(
+            external::decrypt(&key, &nonce[1..], ciphertext, tag),
+            Err(Error::FormatError)
+        ));
+    }
 }
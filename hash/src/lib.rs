@@ -42,7 +42,7 @@ pub trait Hashable {
     fn to_message(&self) -> Vec<u8>;
 }
 
-// Strings and byte vectors are the only types that need to be hashed for this project
+// Strings, byte vectors and IDs are the only types that need to be hashed for this project
 impl Hashable for String {
     fn to_message(&self) -> Vec<u8> {
         self.as_bytes().to_vec()
@@ -55,6 +55,12 @@ impl Hashable for Vec<u8> {
     }
 }
 
+impl Hashable for u64 {
+    fn to_message(&self) -> Vec<u8> {
+        self.to_be_bytes().to_vec()
+    }
+}
+
 /// An enum that contains variants for each of the different hash functions this library provides, also providing the [digest](HashFn::digest) method to create a digest from a message
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub enum HashFn {
@@ -367,6 +367,15 @@ pub mod hash_map {
             assert_eq!(map.get(&String::from("Manonam")), None)
         }
 
+        #[test]
+        fn id_keys() {
+            let mut map = HashMap::new_with_size(5);
+            map.insert(14_u64, "Primm");
+            map.insert(2082_u64, "Manonam");
+            assert_eq!(map.get(&2082).unwrap(), &"Manonam");
+            assert_eq!(map.get(&20), None)
+        }
+
         #[test]
         fn any_struct() {
             struct S {}